@@ -1,10 +1,40 @@
+mod author_works;
+mod bookmarks;
+mod catalog;
+mod cookies;
+pub(crate) mod cover;
+pub(crate) mod download;
+mod download_cache;
+pub(crate) mod epub;
+mod filter;
 mod history;
+mod query;
+pub(crate) mod required_tags;
+mod search;
+mod series;
 mod session;
+mod subscriptions;
+mod tags;
+mod updates;
 pub(crate) mod utils;
 mod work;
+mod work_details;
 
 pub(crate) use self::{
-    history::{HistoryPage, HistoryWork},
+    author_works::AuthorWorksPage,
+    bookmarks::BookmarkPage,
+    catalog::catalog_feed,
+    download::DownloadFormat,
+    download_cache::DownloadCache,
+    filter::{FilterConfig, Filters},
+    history::{HistoryPage, HistoryQuery},
+    query::WorkSearchQuery,
+    search::SearchPage,
+    series::SeriesPage,
     session::{AuthorizedSession, Session},
-    work::{Authors, SeriesRef, Tags, Work},
+    subscriptions::{SubscriptionKind, SubscriptionPage},
+    tags::{browse_feed, tag_works_postfix, TagWorksPage},
+    updates::{ChapterLog, Updates},
+    work::Work,
+    work_details::WorkDetails,
 };
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use color_eyre::Result;
+use scraper::ElementRef;
+
+use crate::opds::OpdsFeed;
+
+use super::{session::AuthorizedSession, utils::*, work::author_feed_uri, Filters, Work};
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuthorWorksPage {
+    user: String,
+    pseud: Option<String>,
+    works: Vec<Work>,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl AuthorWorksPage {
+    pub(crate) fn from_element(
+        element: &ElementRef,
+        user: String,
+        pseud: Option<String>,
+        page: usize,
+    ) -> Result<AuthorWorksPage> {
+        let mut works = Vec::new();
+        for element in select_all(element, "ol.work.index > li.work.blurb") {
+            works.push(Work::from_element(&element)?);
+        }
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(AuthorWorksPage {
+            user,
+            pseud,
+            works,
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) fn works(&self) -> &[Work] {
+        &self.works
+    }
+
+    pub(crate) async fn new(
+        session: &AuthorizedSession,
+        user: String,
+        pseud: Option<String>,
+        page: usize,
+    ) -> Result<AuthorWorksPage> {
+        let html = session
+            .get_author_works_page(&user, pseud.as_deref(), page)
+            .await?;
+        Self::from_element(&html.root_element(), user, pseud, page)
+    }
+}
+
+impl From<(Arc<AuthorWorksPage>, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (Arc<AuthorWorksPage>, &Filters<'_>)) -> Self {
+        let (works, hidden) = filters.apply(&value.works, |w| w);
+        let name = match &value.pseud {
+            Some(pseud) => format!("{} ({})", pseud, value.user),
+            None => value.user.clone(),
+        };
+        OpdsFeed::paginated(
+            &format!(
+                "authors-{}-{}-page-{}",
+                value.user,
+                value.pseud.as_deref().unwrap_or_default(),
+                value.page
+            ),
+            &format!("Works by {}", name),
+            author_feed_uri(&value.user, value.pseud.as_deref()).trim_start_matches("/opds/v1.2/"),
+            works,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        )
+        .with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use scraper::Html;
+
+    use super::AuthorWorksPage;
+    use crate::{
+        ao3::{work::tests::blurb, FilterConfig},
+        opds::OpdsFeed,
+    };
+
+    #[test]
+    fn parses_author_works() {
+        let html = Html::parse_document(&format!(
+            r#"<html><body><h2 class="heading">1 - 20 of 23 Works by Other Name (other)</h2>
+<ol class="work index group">{}{}</ol>
+<ol class="pagination actions" role="navigation">
+<li class="previous"><span class="disabled">← Previous</span></li>
+<li><span class="current">1</span></li>
+<li><a href="/users/other/pseuds/Other%20Name/works?page=2">2</a></li>
+<li class="next"><a rel="next" href="/users/other/pseuds/Other%20Name/works?page=2">Next →</a></li>
+</ol></body></html>"#,
+            blurb("work", 1, "03 Oct 2026", "1/1", ""),
+            blurb("work", 2, "02 Oct 2026", "3/?", "")
+        ));
+        let page = AuthorWorksPage::from_element(
+            &html.root_element(),
+            "other".to_string(),
+            Some("Other Name".to_string()),
+            1,
+        )
+        .unwrap();
+
+        let ids: Vec<_> = page.works().iter().map(|w| w.id()).collect();
+        assert_eq!(ids, [1, 2]);
+        assert!(!page.has_prev);
+        assert!(page.has_next);
+
+        let config = FilterConfig::default();
+        let feed = OpdsFeed::from((Arc::new(page), &config.filters(None, "authors")));
+        assert_eq!(feed.title, "Works by Other Name (other)");
+        let next = feed
+            .links
+            .unwrap()
+            .into_iter()
+            .find(|link| link.rel == "next")
+            .unwrap();
+        assert_eq!(
+            next.href,
+            "/opds/v1.2/authors/other/works?pseud=Other%20Name&page=2"
+        );
+    }
+
+    #[test]
+    fn parses_author_without_works() {
+        let html = Html::parse_document(
+            r#"<html><body><h2 class="heading">0 Works by someone</h2></body></html>"#,
+        );
+        let page =
+            AuthorWorksPage::from_element(&html.root_element(), "someone".to_string(), None, 1)
+                .unwrap();
+
+        assert!(page.works().is_empty());
+        assert!(!page.has_prev);
+        assert!(!page.has_next);
+        let config = FilterConfig::default();
+        let feed = OpdsFeed::from((Arc::new(page), &config.filters(None, "authors")));
+        assert!(feed.entries.is_empty());
+        assert_eq!(feed.title, "Works by someone");
+    }
+}
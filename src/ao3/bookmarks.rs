@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
+use color_eyre::Result;
+use scraper::ElementRef;
+
+use crate::opds::{OpdsEntry, OpdsFeed};
+
+use super::{session::AuthorizedSession, utils::*, Filters, Work};
+
+#[derive(Debug, Clone)]
+pub(crate) struct BookmarkWork {
+    work: Work,
+    bookmarked: DateTime<FixedOffset>,
+    notes: Option<String>,
+    tags: Vec<String>,
+    rec: bool,
+    private: bool,
+}
+
+impl BookmarkWork {
+    pub(crate) fn work(&self) -> &Work {
+        &self.work
+    }
+
+    pub(crate) fn from_element(element: &ElementRef) -> Result<Self> {
+        let user = select_next(element, "div.user")?;
+
+        let bookmarked =
+            select_next_str(&user, "p.datetime").map_or(*DT_DEFAULT, |s| ao3_dt_parse(&s));
+
+        let notes = select_string(&user, "blockquote.notes")
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        let tags = select_all(&user, "ul.meta.tags a.tag")
+            .iter()
+            .filter_map(|a| a.text().next().map(str::to_string))
+            .collect();
+
+        Ok(BookmarkWork {
+            work: Work::from_element(element)?,
+            bookmarked,
+            notes,
+            tags,
+            rec: select_next(&user, "p.status span.rec").is_ok(),
+            private: select_next(&user, "p.status span.private").is_ok(),
+        })
+    }
+
+    /// Bookmarks can also point at series and external works, which don't have a work blurb.
+    fn is_work(element: &ElementRef) -> bool {
+        select_next_attr(element, "h4.heading > a", "href")
+            .is_ok_and(|href| href.starts_with("/works/"))
+    }
+}
+
+impl From<&BookmarkWork> for OpdsEntry {
+    fn from(value: &BookmarkWork) -> Self {
+        let mut entry: OpdsEntry = (&value.work).into();
+
+        let mut flags = Vec::new();
+        if value.rec {
+            flags.push("Rec");
+        }
+        if value.private {
+            flags.push("Private");
+        }
+        let mut line = format!("Bookmarked {}", ao3_dt_format(&value.bookmarked));
+        if !flags.is_empty() {
+            line.push_str(&format!(" ({})", flags.join(", ")));
+        }
+        if !value.tags.is_empty() {
+            line.push_str(&format!("\nBookmark tags: {}", value.tags.join(", ")));
+        }
+        if let Some(notes) = &value.notes {
+            line.push_str(&format!("\nNotes: {}", notes));
+        }
+        entry.push_content(&line);
+        entry
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BookmarkPage {
+    bookmarks: Vec<BookmarkWork>,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl BookmarkPage {
+    pub(crate) fn from_element(element: &ElementRef, page: usize) -> Result<BookmarkPage> {
+        let mut bookmarks = Vec::new();
+
+        for element in select_all(element, "ol.bookmark.index > li.bookmark.blurb") {
+            if !BookmarkWork::is_work(&element) {
+                continue;
+            }
+            bookmarks.push(BookmarkWork::from_element(&element)?);
+        }
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(BookmarkPage {
+            bookmarks,
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) fn bookmarks(&self) -> &[BookmarkWork] {
+        &self.bookmarks
+    }
+
+    pub(crate) async fn new(session: &AuthorizedSession, page: usize) -> Result<BookmarkPage> {
+        let html = session.get_bookmarks_page(page).await?;
+        Self::from_element(&html.root_element(), page)
+    }
+}
+
+impl From<(Arc<BookmarkPage>, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (Arc<BookmarkPage>, &Filters<'_>)) -> Self {
+        let (bookmarks, hidden) = filters.apply(&value.bookmarks, |b| &b.work);
+        OpdsFeed::paginated(
+            &format!("bookmarks-page-{}", value.page),
+            &format!("Bookmarks page {}", value.page),
+            "bookmarks",
+            bookmarks,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        )
+        .with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::BookmarkPage;
+    use crate::{ao3::work::tests::blurb, opds::OpdsEntry};
+
+    const BOOKMARK: &str = r#"<div class="user module group">
+<h5 class="byline heading">Bookmarked by <a href="/users/me/pseuds/me">me</a></h5>
+<p class="datetime">05 Oct 2026</p>
+<p class="status"><span class="rec" title="Rec"></span><span class="private" title="Private Bookmark"></span></p>
+<ul class="meta tags commas"><li><a class="tag" href="/tags/reread">reread</a></li><li><a class="tag" href="/tags/cozy">cozy</a></li></ul>
+<blockquote class="userstuff notes"><p>Lovely.</p></blockquote>
+</div>"#;
+
+    #[test]
+    fn parses_work_bookmarks() {
+        let html = Html::parse_document(&format!(
+            r#"<html><body><ol class="bookmark index group">{}
+<li id="bookmark_2" class="bookmark blurb group" role="article">
+<div class="header module"><h4 class="heading"><a href="/series/7">A Series</a></h4></div>
+</li>
+</ol></body></html>"#,
+            blurb("bookmark", 1, "03 Oct 2026", "1/1", BOOKMARK)
+        ));
+        let page = BookmarkPage::from_element(&html.root_element(), 1).unwrap();
+
+        // the series bookmark is skipped
+        assert_eq!(page.bookmarks.len(), 1);
+        let bookmark = &page.bookmarks[0];
+        assert_eq!(bookmark.work.id(), 1);
+        assert_eq!(
+            bookmark.bookmarked.to_rfc3339(),
+            "2026-10-05T00:00:00+00:00"
+        );
+        assert_eq!(bookmark.tags, ["reread", "cozy"]);
+        assert_eq!(bookmark.notes.as_deref(), Some("Lovely."));
+        assert!(bookmark.rec);
+        assert!(bookmark.private);
+
+        let content = OpdsEntry::from(bookmark).content.unwrap();
+        assert!(content.ends_with(
+            "Bookmarked 5 Oct 2026 (Rec, Private)\nBookmark tags: reread, cozy\nNotes: Lovely."
+        ));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::opds::{CatalogEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+use super::{tags::BROWSE, SubscriptionKind};
+
+const SEARCH: CatalogEntry = CatalogEntry {
+    id: "search",
+    title: "Search",
+    description: "Search works on AO3",
+    href: "/opds/v1.2/search/opensearch.xml",
+    kind: OpdsLinkType::Search,
+};
+
+/// The feeds listed in the root catalog.
+///
+/// Feeds with navigation feeds of their own, like [`BROWSE`], bring their entries along, so the
+/// catalog links them the same way they describe themselves.
+const CATALOG: [CatalogEntry; 7] = [
+    CatalogEntry {
+        id: "history",
+        title: "History",
+        description: "Works you have visited, most recent first",
+        href: "/opds/v1.2/history?page=1",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "updates",
+        title: "Updated since I last read",
+        description: "Works in your history with updates available, most recently updated first",
+        href: "/opds/v1.2/updates",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "bookmarks",
+        title: "Bookmarks",
+        description: "Works you have bookmarked",
+        href: "/opds/v1.2/bookmarks?page=1",
+        kind: OpdsLinkType::Acquisition,
+    },
+    SubscriptionKind::CATALOG_ENTRY,
+    CatalogEntry {
+        id: "later",
+        title: "Marked for Later",
+        description: "Works you have marked for later",
+        href: "/opds/v1.2/later?page=1",
+        kind: OpdsLinkType::Acquisition,
+    },
+    SEARCH,
+    BROWSE,
+];
+
+/// The root navigation feed, which the `start` link of every feed points to.
+pub(crate) fn catalog_feed() -> OpdsFeed {
+    let mut feed = OpdsFeed::navigation("catalog", "Archive of Our Own", "catalog", &CATALOG);
+    feed.links.get_or_insert_with(Vec::new).push(OpdsLink::new(
+        SEARCH.kind,
+        OpdsLinkRel::Search,
+        SEARCH.href.to_string(),
+    ));
+    feed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::catalog_feed;
+    use crate::ao3::{browse_feed, tags::MEDIA};
+
+    #[test]
+    fn links_browse_feed_of_media() {
+        let catalog = catalog_feed();
+        let browse = catalog
+            .entries
+            .iter()
+            .find(|entry| entry.id == "browse")
+            .unwrap();
+        assert_eq!(browse.links[0].href, "/opds/v1.2/browse");
+
+        let media: Vec<_> = browse_feed()
+            .entries
+            .iter()
+            .map(|entry| entry.title.clone())
+            .collect();
+        assert_eq!(media, MEDIA.map(|media| media.title.to_string()));
+    }
+}
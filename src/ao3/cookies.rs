@@ -0,0 +1,106 @@
+use std::{io::ErrorKind, path::PathBuf, sync::Arc};
+
+use color_eyre::Result;
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    Url,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Persists the AO3 cookies of a [`Jar`] to a file, so logins survive restarts.
+///
+/// The file holds the cookies as a single `Cookie` header line, which keeps
+/// `_otwarchive_session` and `remember_user_token` but drops their expiry.
+/// Whether they are still valid is checked against AO3 after loading.
+#[derive(Debug, Clone)]
+pub(crate) struct CookieFile {
+    path: PathBuf,
+    url: Url,
+    jar: Arc<Jar>,
+}
+
+impl CookieFile {
+    pub(crate) fn new(path: PathBuf, url: Url) -> Self {
+        Self {
+            path,
+            url,
+            jar: Arc::new(Jar::default()),
+        }
+    }
+
+    pub(crate) fn jar(&self) -> Arc<Jar> {
+        self.jar.clone()
+    }
+
+    /// Loads the cookies into the jar, returning whether there were any.
+    pub(crate) async fn load(&self) -> Result<bool> {
+        let header = match fs::read_to_string(&self.path).await {
+            Ok(header) => header,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let mut loaded = false;
+        for cookie in header.trim().split("; ").filter(|c| !c.is_empty()) {
+            self.jar
+                .add_cookie_str(&format!("{}; Path=/; Secure", cookie), &self.url);
+            loaded = true;
+        }
+        Ok(loaded)
+    }
+
+    /// Saves the cookies, readable only by us because they log in as the AO3 user.
+    pub(crate) async fn save(&self) -> Result<()> {
+        let header = match self.jar.cookies(&self.url) {
+            Some(header) => header.to_str()?.to_string(),
+            None => String::new(),
+        };
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&self.path).await?;
+        // files from before were created with the default permissions
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await?;
+        }
+        file.write_all(header.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{cookie::CookieStore, Url};
+
+    use super::CookieFile;
+
+    #[tokio::test]
+    async fn round_trips_cookies() {
+        let path = std::env::temp_dir().join(format!("ao3-opds-cookies-{}", std::process::id()));
+        let url = Url::parse("https://archiveofourown.org").unwrap();
+
+        let saved = CookieFile::new(path.clone(), url.clone());
+        assert!(!saved.load().await.unwrap());
+        saved
+            .jar()
+            .add_cookie_str("_otwarchive_session=abc; Path=/; Secure", &url);
+        saved.save().await.unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = CookieFile::new(path.clone(), url.clone());
+        assert!(loaded.load().await.unwrap());
+        let cookies = loaded.jar().cookies(&url).unwrap();
+        assert_eq!(cookies.to_str().unwrap(), "_otwarchive_session=abc");
+        std::fs::remove_file(path).unwrap();
+    }
+}
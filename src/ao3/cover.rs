@@ -0,0 +1,357 @@
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use color_eyre::{eyre::eyre, Result};
+use jpeg_encoder::{ColorType, Encoder};
+use lazy_static::lazy_static;
+
+use super::required_tags::Rating;
+use crate::opds::{OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+lazy_static! {
+    static ref REGULAR: FontRef<'static> =
+        FontRef::try_from_slice(include_bytes!("../../assets/fonts/DejaVuSans.ttf"))
+            .expect("bundled font should be valid");
+    static ref BOLD: FontRef<'static> =
+        FontRef::try_from_slice(include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"))
+            .expect("bundled font should be valid");
+}
+
+/// Covers are laid out on this width, and scaled to the size they are rendered at.
+const WIDTH: f32 = 600.0;
+const MARGIN: f32 = 48.0;
+
+const BACKGROUND: [u8; 3] = [0xf4, 0xf1, 0xea];
+const TEXT: [u8; 3] = [0x2a, 0x2a, 0x2a];
+const MUTED: [u8; 3] = [0x6b, 0x66, 0x5e];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum CoverSize {
+    Full,
+    Thumbnail,
+}
+
+impl CoverSize {
+    pub(crate) fn file_name(&self) -> &'static str {
+        match self {
+            CoverSize::Full => "cover.jpg",
+            CoverSize::Thumbnail => "thumbnail.jpg",
+        }
+    }
+
+    /// Width and height in pixels, in the 2:3 ratio of most book covers.
+    fn dimensions(&self) -> (u16, u16) {
+        match self {
+            CoverSize::Full => (600, 900),
+            CoverSize::Thumbnail => (200, 300),
+        }
+    }
+}
+
+/// Links to the generated cover of a work and its thumbnail.
+pub(crate) fn cover_links(id: i64) -> [OpdsLink; 2] {
+    [
+        (CoverSize::Full, OpdsLinkRel::Image),
+        (CoverSize::Thumbnail, OpdsLinkRel::Thumbnail),
+    ]
+    .map(|(size, rel)| {
+        OpdsLink::new(
+            OpdsLinkType::Image,
+            rel,
+            format!("/opds/v1.2/works/{}/{}", id, size.file_name()),
+        )
+    })
+}
+
+/// The colour AO3 uses for the rating symbol.
+fn rating_colour(rating: Option<Rating>) -> [u8; 3] {
+    match rating {
+        Some(Rating::General) => [0x77, 0xa8, 0x01],
+        Some(Rating::Teen) => [0xe8, 0xd4, 0x05],
+        Some(Rating::Mature) => [0xf0, 0x9c, 0x00],
+        Some(Rating::Explicit) => [0x9c, 0x00, 0x00],
+        Some(Rating::NotRated) | None => [0x8a, 0x8a, 0x8a],
+    }
+}
+
+/// Formats a number with thousands separators, like AO3 does.
+fn thousands(n: i32) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    if n < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+/// What goes on the generated cover of a work.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Cover {
+    pub(crate) title: String,
+    pub(crate) authors: String,
+    pub(crate) fandoms: Vec<String>,
+    pub(crate) rating: Option<Rating>,
+    pub(crate) words: i32,
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    scale: f32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u16, height: u16) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        Canvas {
+            width,
+            height,
+            scale: width as f32 / WIDTH,
+            pixels: BACKGROUND.repeat(width * height),
+        }
+    }
+
+    /// Fills a rectangle given in layout coordinates.
+    fn fill(&mut self, x: f32, y: f32, w: f32, h: f32, colour: [u8; 3]) {
+        let x0 = (x * self.scale).round().max(0.0) as usize;
+        let y0 = (y * self.scale).round().max(0.0) as usize;
+        let x1 = (((x + w) * self.scale).round() as usize).min(self.width);
+        let y1 = (((y + h) * self.scale).round() as usize).min(self.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = (y * self.width + x) * 3;
+                self.pixels[i..i + 3].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    /// Blends `colour` over the pixel with the given coverage.
+    fn blend(&mut self, x: i64, y: i64, coverage: f32, colour: [u8; 3]) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let i = (y as usize * self.width + x as usize) * 3;
+        let coverage = coverage.clamp(0.0, 1.0);
+        for (pixel, c) in self.pixels[i..i + 3].iter_mut().zip(colour) {
+            *pixel = (*pixel as f32 * (1.0 - coverage) + c as f32 * coverage).round() as u8;
+        }
+    }
+
+    /// The width of a line of text in layout coordinates.
+    fn measure(font: &FontRef<'static>, size: f32, text: &str) -> f32 {
+        let font = font.as_scaled(PxScale::from(size));
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                width += font.kern(previous, id);
+            }
+            width += font.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    }
+
+    /// Draws one line of text with its top left corner at the given layout coordinates.
+    fn text(
+        &mut self,
+        font: &FontRef<'static>,
+        size: f32,
+        x: f32,
+        y: f32,
+        text: &str,
+        colour: [u8; 3],
+    ) {
+        let scaled = font.as_scaled(PxScale::from(size * self.scale));
+        let mut caret = point(x * self.scale, y * self.scale + scaled.ascent());
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                caret.x += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(scaled.scale(), caret);
+            caret.x += scaled.h_advance(id);
+            previous = Some(id);
+
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    self.blend(
+                        bounds.min.x as i64 + gx as i64,
+                        bounds.min.y as i64 + gy as i64,
+                        coverage,
+                        colour,
+                    )
+                });
+            }
+        }
+    }
+
+    /// Draws text wrapped to `width`, at most `max_lines` lines, returning the y below it.
+    #[allow(clippy::too_many_arguments)]
+    fn paragraph(
+        &mut self,
+        font: &FontRef<'static>,
+        size: f32,
+        y: f32,
+        width: f32,
+        max_lines: usize,
+        text: &str,
+        colour: [u8; 3],
+    ) -> f32 {
+        let line_height = size * 1.25;
+        let lines = wrap(font, size, width, max_lines, text);
+        for (i, line) in lines.iter().enumerate() {
+            self.text(font, size, MARGIN, y + i as f32 * line_height, line, colour);
+        }
+        y + lines.len() as f32 * line_height
+    }
+}
+
+/// Splits text into lines no wider than `width`, ending the last line with an ellipsis when it
+/// doesn't fit in `max_lines`.
+fn wrap(
+    font: &FontRef<'static>,
+    size: f32,
+    width: f32,
+    max_lines: usize,
+    text: &str,
+) -> Vec<String> {
+    let fits = |line: &str| Canvas::measure(font, size, line) <= width;
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if fits(&candidate) {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // words too long for a line on their own are broken anywhere
+        for c in word.chars() {
+            line.push(c);
+            if !fits(&line) {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.last_mut().expect("max_lines should not be 0");
+        while !last.is_empty() && !fits(&format!("{}…", last)) {
+            last.pop();
+        }
+        *last = format!("{}…", last.trim_end());
+    }
+    lines
+}
+
+impl Cover {
+    /// Renders the cover as a JPEG. The same metadata always gives the same image.
+    pub(crate) fn render(&self, size: CoverSize) -> Result<Vec<u8>> {
+        let (width, height) = size.dimensions();
+        let mut canvas = Canvas::new(width, height);
+        let layout_height = height as f32 / canvas.scale;
+        let text_width = WIDTH - 2.0 * MARGIN;
+        let colour = rating_colour(self.rating);
+
+        canvas.fill(0.0, 0.0, WIDTH, 36.0, colour);
+        canvas.fill(0.0, layout_height - 12.0, WIDTH, 12.0, colour);
+
+        let mut y = 72.0;
+        if !self.fandoms.is_empty() {
+            y = canvas.paragraph(
+                &REGULAR,
+                20.0,
+                y,
+                text_width,
+                2,
+                &self.fandoms.join(", ").to_uppercase(),
+                MUTED,
+            );
+        }
+        canvas.fill(MARGIN, y + 24.0, 96.0, 4.0, colour);
+
+        y = canvas.paragraph(&BOLD, 52.0, y + 64.0, text_width, 6, &self.title, TEXT);
+        if !self.authors.is_empty() {
+            canvas.paragraph(
+                &REGULAR,
+                30.0,
+                y + 28.0,
+                text_width,
+                2,
+                &format!("by {}", self.authors),
+                TEXT,
+            );
+        }
+
+        let words = format!("{} words", thousands(self.words));
+        canvas.text(
+            &REGULAR,
+            22.0,
+            MARGIN,
+            layout_height - 12.0 - 60.0,
+            &words,
+            MUTED,
+        );
+
+        let mut jpeg = Vec::new();
+        Encoder::new(&mut jpeg, 90)
+            .encode(&canvas.pixels, width, height, ColorType::Rgb)
+            .map_err(|e| eyre!("could not encode cover: {}", e))?;
+        Ok(jpeg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{thousands, wrap, Cover, CoverSize, REGULAR};
+    use crate::ao3::required_tags::Rating;
+
+    #[test]
+    fn wraps_and_ellipsises() {
+        let lines = wrap(
+            &REGULAR,
+            20.0,
+            120.0,
+            2,
+            "one two three four five six seven eight",
+        );
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with('…'));
+        assert_eq!(wrap(&REGULAR, 20.0, 200.0, 2, "short"), ["short"]);
+    }
+
+    #[test]
+    fn renders_deterministic_jpegs() {
+        let cover = Cover {
+            title: "A Rather Long Title That Wraps Over Several Lines".to_string(),
+            authors: "someone, Other Name (other)".to_string(),
+            fandoms: vec!["Some Fandom".to_string()],
+            rating: Some(Rating::Teen),
+            words: 123456,
+        };
+        let jpeg = cover.render(CoverSize::Thumbnail).unwrap();
+        assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+        assert_eq!(jpeg, cover.render(CoverSize::Thumbnail).unwrap());
+        assert_eq!(thousands(123456), "123,456");
+    }
+}
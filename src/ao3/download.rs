@@ -0,0 +1,196 @@
+use std::{fmt, ops::Range};
+
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+
+use crate::opds::{OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+use super::{utils::query_value, work::Chapters};
+
+/// The formats AO3 offers works for download in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DownloadFormat {
+    Epub,
+    Azw3,
+    Mobi,
+    Pdf,
+    Html,
+}
+
+impl DownloadFormat {
+    pub(crate) fn from_extension(extension: &str) -> Option<DownloadFormat> {
+        Self::ALL
+            .iter()
+            .find(|format| format.extension() == extension)
+            .copied()
+    }
+
+    pub(crate) const ALL: [DownloadFormat; 5] =
+        [Self::Epub, Self::Azw3, Self::Mobi, Self::Pdf, Self::Html];
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            DownloadFormat::Epub => "epub",
+            DownloadFormat::Azw3 => "azw3",
+            DownloadFormat::Mobi => "mobi",
+            DownloadFormat::Pdf => "pdf",
+            DownloadFormat::Html => "html",
+        }
+    }
+
+    pub(crate) fn link_type(&self) -> OpdsLinkType {
+        match self {
+            DownloadFormat::Epub => OpdsLinkType::Epub,
+            DownloadFormat::Azw3 => OpdsLinkType::Azw3,
+            DownloadFormat::Mobi => OpdsLinkType::Mobi,
+            DownloadFormat::Pdf => OpdsLinkType::Pdf,
+            DownloadFormat::Html => OpdsLinkType::Html,
+        }
+    }
+}
+
+/// A file name for a download of the work, made of the title's letters and digits.
+pub(crate) fn download_filename(title: &str, format: DownloadFormat) -> String {
+    let mut name = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let mut name: String = name.trim_end_matches('_').chars().take(64).collect();
+    if name.is_empty() {
+        name.push_str("work");
+    }
+    format!("{}.{}", name, format.extension())
+}
+
+/// Identifies a version of a work, so cached downloads of older versions are not served.
+pub(crate) fn revision(updated: DateTime<FixedOffset>, chapters: &Chapters) -> String {
+    format!("{}-{}", updated.format("%Y%m%d"), chapters.written())
+}
+
+/// Whether `rev` looks like a [`revision`]: eight digits of date, a dash and the chapter count.
+pub(crate) fn is_revision(rev: &str) -> bool {
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    match rev.split_once('-') {
+        Some((date, chapters)) => date.len() == 8 && all_digits(date) && all_digits(chapters),
+        None => false,
+    }
+}
+
+/// One acquisition link per download format of a work, through our download proxy.
+///
+/// The work's [`revision`] only changes the link along with the work, so clients don't keep
+/// an old file. The proxy looks the revision up itself.
+pub(crate) fn download_links(
+    id: impl fmt::Display,
+    title: &str,
+    revision: Option<&str>,
+) -> Vec<OpdsLink> {
+    let mut query = format!("name={}", query_value(title));
+    if let Some(revision) = revision {
+        query.push_str("&rev=");
+        query.push_str(&query_value(revision));
+    }
+    DownloadFormat::ALL
+        .iter()
+        .map(|format| {
+            OpdsLink::new(
+                format.link_type(),
+                OpdsLinkRel::Acquisition,
+                format!(
+                    "/opds/v1.2/works/{}/download.{}?{}",
+                    id,
+                    format.extension(),
+                    query
+                ),
+            )
+        })
+        .collect()
+}
+
+/// A `Range` header resolved against the length of a file.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    Satisfiable(Range<u64>),
+    /// The range is valid, but none of it is in the file.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header with a single range, like `bytes=100-199`, `bytes=100-` or
+/// `bytes=-100`, into a range of the `len` bytes of a file.
+///
+/// Malformed headers and multiple ranges give `None`, and should be ignored by serving the whole
+/// file.
+pub(crate) fn parse_range(range: &str, len: u64) -> Option<ByteRange> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => len.saturating_sub(suffix.parse().ok()?)..len,
+        (start, "") => start.parse().ok()?..len,
+        (start, end) => {
+            let (start, end) = (start.parse().ok()?, end.parse::<u64>().ok()?);
+            if start > end {
+                return None;
+            }
+            start..(end + 1).min(len)
+        }
+    };
+    Some(if range.start < range.end {
+        ByteRange::Satisfiable(range)
+    } else {
+        ByteRange::Unsatisfiable
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{download_filename, is_revision, parse_range, ByteRange, DownloadFormat};
+
+    #[test]
+    fn makes_filenames_from_titles() {
+        assert_eq!(
+            download_filename("Don't Stop Me Now!", DownloadFormat::Azw3),
+            "Don_t_Stop_Me_Now.azw3"
+        );
+        assert_eq!(download_filename("水", DownloadFormat::Epub), "work.epub");
+    }
+
+    #[test]
+    fn validates_revisions() {
+        assert!(is_revision("20261003-4"));
+        assert!(!is_revision("2026103-4"));
+        assert!(!is_revision("20261003-"));
+        assert!(!is_revision("20261003-4-1"));
+        assert!(!is_revision("../../etc/passwd"));
+        assert!(!is_revision("20261003-/../4"));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let satisfiable = |range| Some(ByteRange::Satisfiable(range));
+        assert_eq!(parse_range("bytes=0-99", 1000), satisfiable(0..100));
+        assert_eq!(parse_range("bytes=900-", 1000), satisfiable(900..1000));
+        assert_eq!(parse_range("bytes=-100", 1000), satisfiable(900..1000));
+        assert_eq!(parse_range("bytes=950-2000", 1000), satisfiable(950..1000));
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            Some(ByteRange::Unsatisfiable)
+        );
+        assert_eq!(
+            parse_range("bytes=2000-2999", 1000),
+            Some(ByteRange::Unsatisfiable)
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_ranges() {
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(parse_range("bytes=9-5", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=-", 1000), None);
+        assert_eq!(parse_range("items=0-99", 1000), None);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use color_eyre::Result;
+
+use super::download::{is_revision, DownloadFormat};
+
+/// A download on disk, named `{id}-{revision}.{extension}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    id: i64,
+    revision: String,
+    format: DownloadFormat,
+}
+
+impl CacheKey {
+    fn file_name(&self) -> String {
+        format!("{}-{}.{}", self.id, self.revision, self.format.extension())
+    }
+
+    fn from_file_name(name: &str) -> Option<CacheKey> {
+        let (stem, extension) = name.rsplit_once('.')?;
+        let (id, revision) = stem.split_once('-')?;
+        if !is_revision(revision) {
+            return None;
+        }
+        Some(CacheKey {
+            id: id.parse().ok()?,
+            revision: revision.to_string(),
+            format: DownloadFormat::from_extension(extension)?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheIndex {
+    /// The size of each file and when it was last used, by a counter.
+    files: HashMap<CacheKey, (u64, u64)>,
+    pinned: HashSet<i64>,
+    clock: u64,
+}
+
+impl CacheIndex {
+    fn touch(&mut self, key: &CacheKey) -> bool {
+        self.clock += 1;
+        match self.files.get_mut(key) {
+            Some((_, used)) => {
+                *used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the least recently used files of works which aren't pinned until the total size
+    /// fits, returning them.
+    fn evict(&mut self, max_bytes: u64) -> Vec<CacheKey> {
+        let mut total: u64 = self.files.values().map(|(size, _)| size).sum();
+        let mut candidates: Vec<_> = self
+            .files
+            .iter()
+            .filter(|(key, _)| !self.pinned.contains(&key.id))
+            .map(|(key, (size, used))| (*used, *size, key.clone()))
+            .collect();
+        candidates.sort_by_key(|(used, _, _)| *used);
+
+        let mut evicted = Vec::new();
+        for (_, size, key) in candidates {
+            if total <= max_bytes {
+                break;
+            }
+            total -= size;
+            self.files.remove(&key);
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// Downloads kept on disk, by work revision, so they can be served without AO3.
+///
+/// Old revisions are replaced when a work is downloaded again, and the least recently used
+/// downloads are removed when the cache grows past its size. Pinned works are never removed.
+#[derive(Debug)]
+pub(crate) struct DownloadCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<CacheIndex>,
+    /// Numbers the partial files of concurrent writes, so they don't overwrite each other.
+    writes: AtomicU64,
+}
+
+impl DownloadCache {
+    /// Opens the cache in `dir`, creating it if needed and indexing the downloads already in it.
+    pub(crate) fn open(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut index = CacheIndex::default();
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if let Some(key) = CacheKey::from_file_name(name) {
+                files.push((metadata.modified()?, metadata.len(), key));
+            } else if name.ends_with(".partial") {
+                // left behind by writes that were interrupted
+                fs::remove_file(entry.path())?;
+            }
+        }
+        // files are used in order of their modification, which is when they were downloaded
+        files.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, key) in files {
+            index.clock += 1;
+            index.files.insert(key, (size, index.clock));
+        }
+
+        match fs::read_to_string(dir.join("pinned")) {
+            Ok(pinned) => index.pinned = pinned.lines().filter_map(|l| l.parse().ok()).collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(DownloadCache {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheIndex> {
+        self.index.lock().expect("download cache lock poisoned")
+    }
+
+    /// The cached download of this revision of the work.
+    pub(crate) async fn get(
+        &self,
+        id: i64,
+        format: DownloadFormat,
+        revision: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = CacheKey {
+            id,
+            revision: revision.to_string(),
+            format,
+        };
+        if !self.lock().touch(&key) {
+            return Ok(None);
+        }
+        Ok(Some(tokio::fs::read(self.dir.join(key.file_name())).await?))
+    }
+
+    /// The most recently used download of any revision of the work, for when AO3 is down.
+    pub(crate) async fn latest(&self, id: i64, format: DownloadFormat) -> Result<Option<Vec<u8>>> {
+        let key = {
+            let index = self.lock();
+            index
+                .files
+                .iter()
+                .filter(|(key, _)| key.id == id && key.format == format)
+                .max_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone())
+        };
+        match key {
+            Some(key) => Ok(Some(tokio::fs::read(self.dir.join(key.file_name())).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores a download, replacing other revisions of it and evicting old downloads.
+    pub(crate) async fn insert(
+        &self,
+        id: i64,
+        format: DownloadFormat,
+        revision: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let key = CacheKey {
+            id,
+            revision: revision.to_string(),
+            format,
+        };
+        let path = self.dir.join(key.file_name());
+        // written next to the final file first, so readers never see half a download
+        let write = self.writes.fetch_add(1, Ordering::Relaxed);
+        let partial = path.with_extension(format!("{}.partial", write));
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        let removed = {
+            let mut index = self.lock();
+            let mut removed: Vec<_> = index
+                .files
+                .keys()
+                .filter(|other| other.id == id && other.format == format && **other != key)
+                .cloned()
+                .collect();
+            for other in &removed {
+                index.files.remove(other);
+            }
+            index.files.insert(key.clone(), (data.len() as u64, 0));
+            index.touch(&key);
+            removed.extend(index.evict(self.max_bytes));
+            removed
+        };
+        for key in removed {
+            tokio::fs::remove_file(self.dir.join(key.file_name())).await?;
+        }
+        Ok(())
+    }
+
+    /// Pins or unpins a work, keeping its downloads however long ago they were used.
+    pub(crate) async fn pin(&self, id: i64, pinned: bool) -> Result<()> {
+        let contents = {
+            let mut index = self.lock();
+            if pinned {
+                index.pinned.insert(id);
+            } else {
+                index.pinned.remove(&id);
+            }
+            index
+                .pinned
+                .iter()
+                .map(|id| format!("{}\n", id))
+                .collect::<String>()
+        };
+        tokio::fs::write(self.dir.join("pinned"), contents).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheIndex, CacheKey};
+    use crate::ao3::DownloadFormat;
+
+    fn key(id: i64) -> CacheKey {
+        CacheKey {
+            id,
+            revision: "20261003-4".to_string(),
+            format: DownloadFormat::Epub,
+        }
+    }
+
+    #[test]
+    fn round_trips_file_names() {
+        assert_eq!(key(12).file_name(), "12-20261003-4.epub");
+        assert_eq!(
+            CacheKey::from_file_name("12-20261003-4.epub"),
+            Some(key(12))
+        );
+        assert_eq!(CacheKey::from_file_name("pinned"), None);
+        assert_eq!(
+            CacheKey::from_file_name("12-20261003-4.epub.0.partial"),
+            None
+        );
+        assert_eq!(CacheKey::from_file_name("12-x.epub"), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_unpinned() {
+        let mut index = CacheIndex::default();
+        for id in 1..=3 {
+            index.files.insert(key(id), (100, 0));
+            index.touch(&key(id));
+        }
+        index.pinned.insert(1);
+        index.touch(&key(2));
+
+        assert_eq!(index.evict(200), [key(3)]);
+        assert_eq!(index.evict(100), [key(2)]);
+        assert_eq!(index.evict(0), []);
+    }
+}
@@ -0,0 +1,478 @@
+use std::io::{Cursor, Read, Write};
+
+use color_eyre::{eyre::eyre, Result};
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::cover::{Cover, CoverSize};
+
+/// Files we add next to the package document, named so they don't clash with AO3's.
+const COVER_IMAGE: &str = "ao3-opds-cover.jpg";
+const COVER_PAGE: &str = "ao3-opds-cover.xhtml";
+const COVER_IMAGE_ID: &str = "ao3-opds-cover";
+const COVER_PAGE_ID: &str = "ao3-opds-cover-page";
+
+/// `meta` names and properties we set ourselves, so AO3's are dropped.
+const REPLACED_META: [&str; 9] = [
+    "cover",
+    "calibre:series",
+    "calibre:series_index",
+    "calibre:title_sort",
+    "belongs-to-collection",
+    "collection-type",
+    "group-position",
+    "role",
+    "file-as",
+];
+
+/// The EPUB version of a package document, which decides the markup of our metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackageVersion {
+    /// OPF 2.0, where metadata is refined with `opf:` attributes.
+    Epub2,
+    /// OPF 3.0, where metadata is refined with `meta` elements.
+    Epub3,
+}
+
+impl PackageVersion {
+    fn of(package: &BytesStart) -> Result<Self> {
+        Ok(match attribute(package, "version")? {
+            Some(version) if version.starts_with('3') => PackageVersion::Epub3,
+            _ => PackageVersion::Epub2,
+        })
+    }
+}
+
+/// What we write into the package document of a proxied EPUB.
+#[derive(Debug, Clone)]
+pub(crate) struct EpubMetadata {
+    pub(crate) title: String,
+    pub(crate) authors: Vec<String>,
+    /// Every tag of the work, from the rating down to the additional tags.
+    pub(crate) subjects: Vec<String>,
+    /// The first series the work is in, with its part.
+    pub(crate) series: Option<(String, i32)>,
+    pub(crate) cover: Cover,
+}
+
+/// Sorts titles without their leading article, like libraries do.
+fn title_sort(title: &str) -> String {
+    let title = title.trim();
+    for article in ["The ", "A ", "An "] {
+        if let Some(rest) = title
+            .get(..article.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(article))
+            .map(|_| &title[article.len()..])
+        {
+            return rest.trim_start().to_string();
+        }
+    }
+    title.to_string()
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(match element.try_get_attribute(name)? {
+        Some(attribute) => Some(attribute.unescape_value()?.into_owned()),
+        None => None,
+    })
+}
+
+/// Whether an element of the package metadata is one we replace.
+fn is_replaced(element: &BytesStart) -> Result<bool> {
+    Ok(match element.local_name().as_ref() {
+        b"title" | b"creator" | b"subject" => true,
+        b"meta" => {
+            let name = attribute(element, "name")?;
+            let property = attribute(element, "property")?;
+            [name, property]
+                .iter()
+                .flatten()
+                .any(|n| REPLACED_META.contains(&n.as_str()))
+        }
+        _ => false,
+    })
+}
+
+fn write_element<W: Write>(
+    writer: &mut Writer<W>,
+    name: &str,
+    attributes: &[(&str, &str)],
+    text: Option<&str>,
+) -> Result<()> {
+    let start = BytesStart::new(name).with_attributes(attributes.iter().copied());
+    match text {
+        Some(text) => {
+            writer.write_event(Event::Start(start))?;
+            writer.write_event(Event::Text(BytesText::new(text)))?;
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+        None => writer.write_event(Event::Empty(start))?,
+    }
+    Ok(())
+}
+
+fn write_metadata<W: Write>(
+    writer: &mut Writer<W>,
+    metadata: &EpubMetadata,
+    version: PackageVersion,
+) -> Result<()> {
+    write_element(writer, "dc:title", &[], Some(&metadata.title))?;
+    write_element(
+        writer,
+        "meta",
+        &[
+            ("name", "calibre:title_sort"),
+            ("content", &title_sort(&metadata.title)),
+        ],
+        None,
+    )?;
+    // AO3 names are handles rather than first and last names, so they sort as they are instead
+    // of being turned around by calibre
+    for (i, author) in metadata.authors.iter().enumerate() {
+        match version {
+            PackageVersion::Epub2 => write_element(
+                writer,
+                "dc:creator",
+                &[("opf:role", "aut"), ("opf:file-as", author)],
+                Some(author),
+            )?,
+            PackageVersion::Epub3 => {
+                let id = format!("ao3-creator-{}", i);
+                let refines = format!("#{}", id);
+                write_element(writer, "dc:creator", &[("id", &id)], Some(author))?;
+                write_element(
+                    writer,
+                    "meta",
+                    &[
+                        ("refines", &refines),
+                        ("property", "role"),
+                        ("scheme", "marc:relators"),
+                    ],
+                    Some("aut"),
+                )?;
+                write_element(
+                    writer,
+                    "meta",
+                    &[("refines", &refines), ("property", "file-as")],
+                    Some(author),
+                )?;
+            }
+        }
+    }
+    for subject in &metadata.subjects {
+        write_element(writer, "dc:subject", &[], Some(subject))?;
+    }
+    if let Some((series, part)) = &metadata.series {
+        let part = part.to_string();
+        // calibre reads its own metadata, EPUB 3 readers like KOReader the collection
+        write_element(
+            writer,
+            "meta",
+            &[("name", "calibre:series"), ("content", series)],
+            None,
+        )?;
+        write_element(
+            writer,
+            "meta",
+            &[("name", "calibre:series_index"), ("content", &part)],
+            None,
+        )?;
+        if version == PackageVersion::Epub3 {
+            write_element(
+                writer,
+                "meta",
+                &[("property", "belongs-to-collection"), ("id", "ao3-series")],
+                Some(series),
+            )?;
+            write_element(
+                writer,
+                "meta",
+                &[("refines", "#ao3-series"), ("property", "collection-type")],
+                Some("series"),
+            )?;
+            write_element(
+                writer,
+                "meta",
+                &[("refines", "#ao3-series"), ("property", "group-position")],
+                Some(&part),
+            )?;
+        }
+    }
+    write_element(
+        writer,
+        "meta",
+        &[("name", "cover"), ("content", COVER_IMAGE_ID)],
+        None,
+    )
+}
+
+fn write_cover_reference<W: Write>(writer: &mut Writer<W>) -> Result<()> {
+    write_element(
+        writer,
+        "reference",
+        &[("type", "cover"), ("title", "Cover"), ("href", COVER_PAGE)],
+        None,
+    )
+}
+
+/// Rewrites the package document with our metadata and the generated cover.
+fn rewrite_opf(opf: &str, metadata: &EpubMetadata) -> Result<String> {
+    let mut reader = Reader::from_str(opf);
+    let mut writer = Writer::new(Vec::new());
+    let mut version = PackageVersion::Epub2;
+    let mut in_metadata = false;
+    let mut has_guide = false;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Eof => break,
+            Event::Start(e) if in_metadata && is_replaced(e)? => {
+                reader.read_to_end(e.name())?;
+                continue;
+            }
+            Event::Empty(e) if in_metadata && is_replaced(e)? => continue,
+            Event::Start(e) if e.local_name().as_ref() == b"package" => {
+                version = PackageVersion::of(e)?
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"metadata" => in_metadata = true,
+            Event::End(e) => match e.local_name().as_ref() {
+                b"metadata" => {
+                    in_metadata = false;
+                    write_metadata(&mut writer, metadata, version)?;
+                }
+                b"manifest" => {
+                    let mut cover = vec![
+                        ("id", COVER_IMAGE_ID),
+                        ("href", COVER_IMAGE),
+                        ("media-type", "image/jpeg"),
+                    ];
+                    if version == PackageVersion::Epub3 {
+                        cover.push(("properties", "cover-image"));
+                    }
+                    write_element(&mut writer, "item", &cover, None)?;
+                    write_element(
+                        &mut writer,
+                        "item",
+                        &[
+                            ("id", COVER_PAGE_ID),
+                            ("href", COVER_PAGE),
+                            ("media-type", "application/xhtml+xml"),
+                        ],
+                        None,
+                    )?;
+                }
+                b"guide" => {
+                    has_guide = true;
+                    write_cover_reference(&mut writer)?;
+                }
+                b"package" if !has_guide => {
+                    writer.write_event(Event::Start(BytesStart::new("guide")))?;
+                    write_cover_reference(&mut writer)?;
+                    writer.write_event(Event::End(BytesEnd::new("guide")))?;
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        writer.write_event(&event)?;
+
+        // the cover goes first in the reading order
+        if let Event::Start(e) = &event {
+            if e.local_name().as_ref() == b"spine" {
+                write_element(&mut writer, "itemref", &[("idref", COVER_PAGE_ID)], None)?;
+            }
+        }
+    }
+
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn cover_page() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Cover</title></head>
+<body style="margin: 0; text-align: center;"><img src="{}" alt="Cover" style="height: 100%; max-width: 100%;"/></body>
+</html>
+"#,
+        COVER_IMAGE
+    )
+}
+
+/// The path of the package document, from the container.
+fn package_path(container: &str) -> Result<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                return attribute(&e, "full-path")?
+                    .ok_or_else(|| eyre!("EPUB rootfile has no path"));
+            }
+            Event::Eof => return Err(eyre!("EPUB has no rootfile")),
+            _ => {}
+        }
+    }
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Rewrites an AO3 EPUB with a generated cover and metadata that libraries can shelve by.
+///
+/// Everything but the package document is copied over as it is.
+pub(crate) fn rewrite_epub(epub: &[u8], metadata: &EpubMetadata) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(epub))?;
+    let opf_path = package_path(&read_file(&mut archive, "META-INF/container.xml")?)?;
+    let opf = rewrite_opf(&read_file(&mut archive, &opf_path)?, metadata)?;
+    // the cover files sit next to the package document, which their hrefs are relative to
+    let dir = opf_path
+        .rsplit_once('/')
+        .map(|(dir, _)| format!("{}/", dir))
+        .unwrap_or_default();
+
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    // the mimetype has to come first, uncompressed
+    out.start_file("mimetype", stored)?;
+    out.write_all(b"application/epub+zip")?;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let name = file.name().to_string();
+        if name == "mimetype" {
+            continue;
+        } else if name == opf_path {
+            out.start_file(name, deflated)?;
+            out.write_all(opf.as_bytes())?;
+        } else {
+            out.raw_copy_file(file)?;
+        }
+    }
+    out.start_file(format!("{}{}", dir, COVER_IMAGE), stored)?;
+    out.write_all(&metadata.cover.render(CoverSize::Full)?)?;
+    out.start_file(format!("{}{}", dir, COVER_PAGE), deflated)?;
+    out.write_all(cover_page().as_bytes())?;
+
+    Ok(out.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+    use super::{rewrite_epub, title_sort, EpubMetadata};
+    use crate::ao3::cover::Cover;
+
+    const OPF: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="uuid_id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+<dc:title>The Work</dc:title>
+<dc:creator opf:role="aut" opf:file-as="Someone">someone</dc:creator>
+<dc:subject>Fanworks</dc:subject>
+<dc:language>en</dc:language>
+</metadata>
+<manifest><item id="ch1" href="chapter1.xhtml" media-type="application/xhtml+xml"/></manifest>
+<spine toc="ncx"><itemref idref="ch1"/></spine>
+</package>"#;
+
+    #[test]
+    fn sorts_titles_without_articles() {
+        assert_eq!(title_sort("The Work"), "Work");
+        assert_eq!(title_sort("an Apple"), "Apple");
+        assert_eq!(title_sort("Another"), "Another");
+    }
+
+    /// Rewrites an EPUB with the given package document, returning the rewritten one.
+    fn rewrite(opf: &str) -> String {
+        let mut epub = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("mimetype", "application/epub+zip"),
+            (
+                "META-INF/container.xml",
+                r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+            ),
+            ("OEBPS/content.opf", opf),
+            ("OEBPS/chapter1.xhtml", "<html/>"),
+        ] {
+            epub.start_file(name, FileOptions::default()).unwrap();
+            epub.write_all(content.as_bytes()).unwrap();
+        }
+        let epub = epub.finish().unwrap().into_inner();
+
+        let metadata = EpubMetadata {
+            title: "The Work".to_string(),
+            authors: vec!["someone".to_string()],
+            subjects: vec!["Teen And Up Audiences".to_string(), "Fluff".to_string()],
+            series: Some(("A Series".to_string(), 2)),
+            cover: Cover {
+                title: "The Work".to_string(),
+                authors: "someone".to_string(),
+                fandoms: Vec::new(),
+                rating: None,
+                words: 1000,
+            },
+        };
+        let rewritten = rewrite_epub(&epub, &metadata).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(rewritten)).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert!(archive.by_name("OEBPS/ao3-opds-cover.jpg").is_ok());
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        opf
+    }
+
+    #[test]
+    fn rewrites_package_metadata() {
+        let opf = rewrite(OPF);
+        assert!(!opf.contains("Fanworks"));
+        assert!(opf.contains("<dc:subject>Fluff</dc:subject>"));
+        assert!(opf.contains(r#"<meta name="calibre:title_sort" content="Work"/>"#));
+        assert!(opf.contains(r#"<meta name="calibre:series_index" content="2"/>"#));
+        assert!(opf.contains(r#"<spine toc="ncx"><itemref idref="ao3-opds-cover-page"/>"#));
+        assert!(
+            opf.contains(r#"<reference type="cover" title="Cover" href="ao3-opds-cover.xhtml"/>"#)
+        );
+        assert_eq!(opf.matches("<dc:creator").count(), 1);
+        assert!(opf.contains("<dc:language>en</dc:language>"));
+    }
+
+    #[test]
+    fn writes_opf2_markup_into_epub2() {
+        let opf = rewrite(OPF);
+        assert!(opf
+            .contains(r#"<dc:creator opf:role="aut" opf:file-as="someone">someone</dc:creator>"#));
+        assert!(opf.contains(r#"<meta name="calibre:series" content="A Series"/>"#));
+        assert!(opf.contains(r#"<meta name="cover" content="ao3-opds-cover"/>"#));
+        assert!(!opf.contains("belongs-to-collection"));
+        assert!(!opf.contains("refines"));
+        assert!(!opf.contains("properties="));
+    }
+
+    #[test]
+    fn writes_opf3_markup_into_epub3() {
+        let opf = rewrite(&OPF.replace(r#"version="2.0""#, r#"version="3.0""#));
+        assert!(opf.contains(r#"<dc:creator id="ao3-creator-0">someone</dc:creator>"#));
+        assert!(opf.contains(
+            r##"<meta refines="#ao3-creator-0" property="role" scheme="marc:relators">aut</meta>"##
+        ));
+        assert!(!opf.contains("opf:role"));
+        assert!(opf
+            .contains(r#"<meta property="belongs-to-collection" id="ao3-series">A Series</meta>"#));
+        assert!(opf.contains(r##"<meta refines="#ao3-series" property="group-position">2</meta>"##));
+        assert!(opf.contains(r#"media-type="image/jpeg" properties="cover-image"/>"#));
+    }
+}
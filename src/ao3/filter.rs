@@ -0,0 +1,319 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path};
+
+use color_eyre::Result;
+use serde::Deserialize;
+
+use super::{
+    download::DownloadFormat,
+    required_tags::{Completion, Rating, Warning},
+};
+
+/// Which works to hide from acquisition feeds.
+///
+/// `include_*` lists keep only works with at least one of the listed values,
+/// `exclude_*` lists hide works with any of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ContentFilter {
+    pub(crate) include_ratings: Vec<Rating>,
+    pub(crate) exclude_ratings: Vec<Rating>,
+    pub(crate) include_warnings: Vec<Warning>,
+    pub(crate) exclude_warnings: Vec<Warning>,
+    pub(crate) include_fandoms: Vec<String>,
+    pub(crate) exclude_fandoms: Vec<String>,
+    pub(crate) include_relationships: Vec<String>,
+    pub(crate) exclude_relationships: Vec<String>,
+    pub(crate) include_freeforms: Vec<String>,
+    pub(crate) exclude_freeforms: Vec<String>,
+    pub(crate) include_languages: Vec<String>,
+    pub(crate) exclude_languages: Vec<String>,
+    pub(crate) min_words: Option<i32>,
+    pub(crate) max_words: Option<i32>,
+    pub(crate) completion: Option<Completion>,
+}
+
+/// Checks a work's values against an include and an exclude list.
+fn include_exclude<'a, T: PartialEq + 'a>(
+    values: impl IntoIterator<Item = &'a T> + Clone,
+    include: &[T],
+    exclude: &[T],
+) -> bool {
+    let included = include.is_empty() || values.clone().into_iter().any(|v| include.contains(v));
+    let excluded = values.into_iter().any(|v| exclude.contains(v));
+    included && !excluded
+}
+
+/// What filters look at in a work, which both blurbs and work pages show.
+#[derive(Debug)]
+pub(crate) struct FilterSubject<'a> {
+    pub(crate) rating: Option<Rating>,
+    pub(crate) warnings: &'a [Warning],
+    pub(crate) fandoms: &'a [String],
+    pub(crate) relationships: &'a [String],
+    pub(crate) freeforms: &'a [String],
+    /// The language name, like `English`.
+    pub(crate) language: &'a str,
+    pub(crate) words: i32,
+    pub(crate) completion: Completion,
+}
+
+/// Works that filters can be applied to.
+pub(crate) trait Filterable {
+    fn filter_subject(&self) -> FilterSubject<'_>;
+}
+
+impl ContentFilter {
+    fn is_empty(&self) -> bool {
+        *self == ContentFilter::default()
+    }
+
+    fn allows(&self, work: &FilterSubject) -> bool {
+        let language = [work.language.to_lowercase()];
+        let languages =
+            |list: &[String]| -> Vec<String> { list.iter().map(|l| l.to_lowercase()).collect() };
+
+        include_exclude(&work.rating, &self.include_ratings, &self.exclude_ratings)
+            && include_exclude(
+                work.warnings,
+                &self.include_warnings,
+                &self.exclude_warnings,
+            )
+            && include_exclude(work.fandoms, &self.include_fandoms, &self.exclude_fandoms)
+            && include_exclude(
+                work.relationships,
+                &self.include_relationships,
+                &self.exclude_relationships,
+            )
+            && include_exclude(
+                work.freeforms,
+                &self.include_freeforms,
+                &self.exclude_freeforms,
+            )
+            && include_exclude(
+                &language,
+                &languages(&self.include_languages),
+                &languages(&self.exclude_languages),
+            )
+            && self.min_words.is_none_or(|min| work.words >= min)
+            && self.max_words.is_none_or(|max| work.words <= max)
+            && self.completion.is_none_or(|c| c == work.completion)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "RawFilterProfile")]
+struct FilterProfile {
+    filter: ContentFilter,
+    /// Filters for single feeds, by name like `history` or `search`.
+    feeds: HashMap<String, ContentFilter>,
+    /// The download formats advertised to the user's clients.
+    formats: Option<Vec<DownloadFormat>>,
+}
+
+/// A [`FilterProfile`] as written in the config, with whatever keys the filter didn't take.
+///
+/// `deny_unknown_fields` doesn't work together with `flatten`, so a misspelled filter key
+/// would otherwise be dropped without a word and the filter would never apply.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RawFilterProfile {
+    #[serde(flatten)]
+    filter: ContentFilter,
+    feeds: HashMap<String, ContentFilter>,
+    formats: Option<Vec<DownloadFormat>>,
+    #[serde(flatten)]
+    unknown: HashMap<String, toml::Value>,
+}
+
+impl TryFrom<RawFilterProfile> for FilterProfile {
+    type Error = String;
+
+    fn try_from(value: RawFilterProfile) -> std::result::Result<Self, Self::Error> {
+        let mut unknown: Vec<_> = value.unknown.into_keys().collect();
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => Err(format!("unknown filter key `{}`", key)),
+            None => Ok(FilterProfile {
+                filter: value.filter,
+                feeds: value.feeds,
+                formats: value.formats,
+            }),
+        }
+    }
+}
+
+/// The filter config file.
+///
+/// ```toml
+/// [default]
+/// exclude_ratings = ["explicit"]
+///
+/// [default.feeds.search]
+/// exclude_warnings = ["noncon"]
+///
+/// [users.alice]
+/// include_languages = ["English"]
+/// max_words = 100000
+/// formats = ["azw3"]
+/// ```
+///
+/// Users are told apart by the username clients send with HTTP basic auth.
+/// Every filter which applies to a request must allow a work for it to be shown.
+/// `formats` are taken from the user, then the default, and are all formats if neither sets them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FilterConfig {
+    default: FilterProfile,
+    users: HashMap<String, FilterProfile>,
+}
+
+impl FilterConfig {
+    /// Loads the config, which is empty if the file doesn't exist.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(config) => Ok(toml::from_str(&config)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(crate) fn filters(&self, user: Option<&str>, feed: &str) -> Filters<'_> {
+        let profiles =
+            std::iter::once(&self.default).chain(user.and_then(|user| self.users.get(user)));
+        let mut filters = Vec::new();
+        for profile in profiles {
+            filters.push(&profile.filter);
+            filters.extend(profile.feeds.get(feed));
+        }
+        Filters(filters)
+    }
+
+    pub(crate) fn formats(&self, user: Option<&str>) -> &[DownloadFormat] {
+        user.and_then(|user| self.users.get(user)?.formats.as_deref())
+            .or(self.default.formats.as_deref())
+            .unwrap_or(&DownloadFormat::ALL)
+    }
+}
+
+/// The filters which apply to one request.
+#[derive(Debug)]
+pub(crate) struct Filters<'a>(Vec<&'a ContentFilter>);
+
+impl<'a> Filters<'a> {
+    /// Whether no filter applies, so every work is allowed.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.iter().all(|filter| filter.is_empty())
+    }
+
+    pub(crate) fn allows(&self, work: &impl Filterable) -> bool {
+        let subject = work.filter_subject();
+        self.0.iter().all(|filter| filter.allows(&subject))
+    }
+
+    /// Keeps the items whose work is allowed, returning them and how many were hidden.
+    pub(crate) fn apply<'b, T, W: Filterable>(
+        &self,
+        items: &'b [T],
+        work: impl Fn(&T) -> &W,
+    ) -> (Vec<&'b T>, usize) {
+        let kept: Vec<_> = items
+            .iter()
+            .filter(|item| self.allows(work(item)))
+            .collect();
+        let hidden = items.len() - kept.len();
+        (kept, hidden)
+    }
+
+    /// Like [`Filters::apply`] for items whose work may not be known, which are kept.
+    pub(crate) fn apply_known<'b, 'c, T, W: Filterable + 'c>(
+        &self,
+        items: &'b [T],
+        work: impl Fn(&T) -> Option<&'c W>,
+    ) -> (Vec<&'b T>, usize) {
+        let kept: Vec<_> = items
+            .iter()
+            .filter(|item| work(item).is_none_or(|work| self.allows(work)))
+            .collect();
+        let hidden = items.len() - kept.len();
+        (kept, hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilterConfig;
+    use crate::ao3::{required_tags::Rating, DownloadFormat};
+
+    #[test]
+    fn stacks_user_and_feed_filters() {
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [default]
+            exclude_ratings = ["explicit"]
+
+            [users.alice.feeds.search]
+            max_words = 1000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.filters(None, "search").0.len(), 1);
+        let filters = config.filters(Some("alice"), "search");
+        assert_eq!(filters.0.len(), 3);
+        assert_eq!(filters.0[0].exclude_ratings, [Rating::Explicit]);
+        assert_eq!(filters.0[2].max_words, Some(1000));
+    }
+
+    #[test]
+    fn skips_feeds_without_filters() {
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [users.alice.feeds.subscriptions]
+            completion = "complete"
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.filters(None, "subscriptions").is_empty());
+        assert!(config.filters(Some("alice"), "history").is_empty());
+        assert!(!config.filters(Some("alice"), "subscriptions").is_empty());
+    }
+
+    #[test]
+    fn rejects_misspelled_filter_keys() {
+        let user = toml::from_str::<FilterConfig>(
+            r#"
+            [users.alice]
+            exclude_warning = ["noncon"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(user.to_string().contains("exclude_warning"));
+
+        let feed = toml::from_str::<FilterConfig>(
+            r#"
+            [default.feeds.search]
+            max_word = 1000
+            "#,
+        )
+        .unwrap_err();
+        assert!(feed.to_string().contains("max_word"));
+    }
+
+    #[test]
+    fn picks_user_formats() {
+        let config: FilterConfig = toml::from_str(
+            r#"
+            [users.kindle]
+            formats = ["azw3", "mobi"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.formats(None), DownloadFormat::ALL);
+        assert_eq!(
+            config.formats(Some("kindle")),
+            [DownloadFormat::Azw3, DownloadFormat::Mobi]
+        );
+    }
+}
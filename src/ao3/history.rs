@@ -1,22 +1,45 @@
-use std::sync::Arc;
+use std::{cmp::Reverse, sync::Arc};
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 use color_eyre::Result;
 use lazy_static::lazy_static;
 use regex::Regex;
 use scraper::ElementRef;
+use serde::Deserialize;
+use url::form_urlencoded;
 
-use crate::opds::{OpdsEntry, OpdsFeed};
+use crate::error::Error;
+use crate::opds::{OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType, OpdsVisit};
 
-use super::{session::AuthorizedSession, utils::*, Work};
+use super::{session::AuthorizedSession, utils::*, Filters, Work};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Changed {
     Latest,
     Minor,
     Updated,
     Unknown(String),
 }
+
+impl Changed {
+    fn as_str(&self) -> &str {
+        match self {
+            Changed::Latest => "latest",
+            Changed::Minor => "minor",
+            Changed::Updated => "updated",
+            Changed::Unknown(other) => other,
+        }
+    }
+
+    fn describe(&self) -> &str {
+        match self {
+            Changed::Latest => "latest version",
+            Changed::Minor => "minor edits since",
+            Changed::Updated => "update available",
+            Changed::Unknown(other) => other,
+        }
+    }
+}
 #[derive(Debug, Clone)]
 pub(crate) struct HistoryWork {
     work: Work,
@@ -32,9 +55,13 @@ lazy_static! {
 
 impl HistoryWork {
     pub(crate) fn from_element(element: &ElementRef) -> Result<Self> {
-        let visited = select_next(element, "div.user > h4")?;
+        const VISITS: &str = "div.user > h4";
+        let parse_error = || Error::Parse {
+            selector: VISITS.to_string(),
+        };
+        let visited = select_next(element, VISITS)?;
         let visited = visited.text().collect::<String>();
-        let caps = HISTORY_RE.captures(&visited).unwrap();
+        let caps = HISTORY_RE.captures(&visited).ok_or_else(parse_error)?;
 
         let last_visited = caps
             .get(1)
@@ -47,17 +74,14 @@ impl HistoryWork {
             other => Changed::Unknown(other.to_string()),
         };
 
-        let visited = caps.get(3).map_or(0, |m| {
-            let s = m.as_str();
-            if s == "once" {
-                1
-            } else {
-                s.split_once(' ').map_or(0, |s| {
-                    s.0.parse()
-                        .unwrap_or_else(|_| panic!("Failed to parse {}", s.1))
-                })
-            }
-        });
+        let visited = match caps.get(3).map(|m| m.as_str()) {
+            Some("once") => 1,
+            Some(times) => times
+                .split_once(' ')
+                .and_then(|(count, _)| count.parse().ok())
+                .ok_or_else(parse_error)?,
+            None => 0,
+        };
 
         Ok(HistoryWork {
             work: Work::from_element(element)?,
@@ -68,22 +92,90 @@ impl HistoryWork {
     }
 }
 
+impl HistoryWork {
+    pub(crate) fn work(&self) -> &Work {
+        &self.work
+    }
+
+    /// Whether AO3 notes the work was updated since the last visit.
+    pub(crate) fn has_update(&self) -> bool {
+        self.changed == Changed::Updated
+    }
+}
+
+impl HistoryWork {
+    /// The entry of the work with its visits, dated by the last visit instead of the work's last
+    /// update with `by_visit`.
+    fn entry(&self, by_visit: bool) -> OpdsEntry {
+        let mut entry: OpdsEntry = (&self.work).into();
+        let visits = match self.visited {
+            1 => "Visited once".to_string(),
+            n => format!("Visited {} times", n),
+        };
+        entry.push_content(&format!(
+            "{}, last {}, {}",
+            visits,
+            ao3_dt_format(&self.last_visited),
+            self.changed.describe()
+        ));
+        entry.visit = Some(OpdsVisit {
+            last: self.last_visited.to_rfc3339(),
+            count: self.visited,
+            changed: self.changed.as_str().to_string(),
+        });
+        if by_visit {
+            entry.updated = self.last_visited.to_rfc3339();
+        }
+        entry
+    }
+}
+
 impl From<&HistoryWork> for OpdsEntry {
     fn from(value: &HistoryWork) -> Self {
-        (&value.work).into() // TODO: maybe add to the OpdsEntry when doing this
+        value.entry(false)
+    }
+}
+
+/// The views of AO3's readings page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Readings {
+    History,
+    Later,
+}
+
+impl Readings {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Readings::History => "history",
+            Readings::Later => "later",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Readings::History => "History",
+            Readings::Later => "Marked for Later",
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct HistoryPage {
+    readings: Readings,
     history: Vec<HistoryWork>,
     page: usize,
+    /// The number of the last page, as far as the pagination shows.
+    last_page: usize,
     has_next: bool,
     has_prev: bool,
 }
 
 impl HistoryPage {
-    pub(crate) fn from_element(element: &ElementRef, page: usize) -> Result<HistoryPage> {
+    pub(crate) fn from_element(
+        element: &ElementRef,
+        readings: Readings,
+        page: usize,
+    ) -> Result<HistoryPage> {
         let mut history = Vec::new();
 
         for element in select_all(element, "ol.reading.index > li.reading.blurb") {
@@ -96,10 +188,16 @@ impl HistoryPage {
         let has_next = select_next(element, "ol.pagination > li.next > a")
             .ok()
             .is_some();
+        let last_page = select_all(element, "ol.pagination > li > a")
+            .iter()
+            .filter_map(|a| a.text().next()?.trim().parse().ok())
+            .fold(page, usize::max);
 
         Ok(HistoryPage {
+            readings,
             history,
             page,
+            last_page,
             has_next,
             has_prev,
         })
@@ -107,20 +205,330 @@ impl HistoryPage {
 
     pub(crate) async fn new(session: &AuthorizedSession, page: usize) -> Result<HistoryPage> {
         let html = session.get_history_page(page).await?;
-        Self::from_element(&html.root_element(), page)
+        Self::from_element(&html.root_element(), Readings::History, page)
+    }
+
+    pub(crate) fn works(&self) -> &[HistoryWork] {
+        &self.history
     }
+
+    pub(crate) fn last_page(&self) -> usize {
+        self.last_page
+    }
+
+    /// The works marked for later, which AO3 shows as a view of the history.
+    pub(crate) async fn later(session: &AuthorizedSession, page: usize) -> Result<HistoryPage> {
+        let html = session.get_later_page(page).await?;
+        Self::from_element(&html.root_element(), Readings::Later, page)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VisitedWithin {
+    Week,
+    Month,
+}
+
+impl VisitedWithin {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VisitedWithin::Week => "week",
+            VisitedWithin::Month => "month",
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        match self {
+            VisitedWithin::Week => Duration::days(7),
+            VisitedWithin::Month => Duration::days(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HistorySort {
+    Visited,
+    Title,
+    Author,
 }
 
-impl From<Arc<HistoryPage>> for OpdsFeed {
-    fn from(value: Arc<HistoryPage>) -> Self {
-        OpdsFeed::paginated(
-            &format!("history-page-{}", value.page),
-            &format!("History page {}", value.page),
-            "history",
-            value.history.iter().collect(),
+impl HistorySort {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HistorySort::Visited => "visited",
+            HistorySort::Title => "title",
+            HistorySort::Author => "author",
+        }
+    }
+}
+
+/// A facet of the history feed, see [`HistoryQuery::toggle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HistoryFacet {
+    Updated,
+    MinVisits(i32),
+    VisitedWithin(VisitedWithin),
+    Sort(HistorySort),
+    ByVisit,
+}
+
+const FACETS: [(&str, &str, HistoryFacet); 10] = [
+    ("Show", "Updates available", HistoryFacet::Updated),
+    (
+        "Visits",
+        "Visited more than once",
+        HistoryFacet::MinVisits(2),
+    ),
+    (
+        "Visits",
+        "Visited more than 5 times",
+        HistoryFacet::MinVisits(6),
+    ),
+    (
+        "Visits",
+        "Visited more than 10 times",
+        HistoryFacet::MinVisits(11),
+    ),
+    (
+        "Last visited",
+        "In the last week",
+        HistoryFacet::VisitedWithin(VisitedWithin::Week),
+    ),
+    (
+        "Last visited",
+        "In the last month",
+        HistoryFacet::VisitedWithin(VisitedWithin::Month),
+    ),
+    (
+        "Sort by",
+        "Last visited",
+        HistoryFacet::Sort(HistorySort::Visited),
+    ),
+    ("Sort by", "Title", HistoryFacet::Sort(HistorySort::Title)),
+    ("Sort by", "Author", HistoryFacet::Sort(HistorySort::Author)),
+    ("Dates", "Date by last visit", HistoryFacet::ByVisit),
+];
+
+/// Narrows and sorts the works of a history page.
+///
+/// AO3 has no such options for the readings page, so they only apply to the works of the fetched
+/// page.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub(crate) struct HistoryQuery {
+    #[serde(default)]
+    updated: bool,
+    /// Only works visited at least this many times.
+    visits: Option<i32>,
+    within: Option<VisitedWithin>,
+    sort: Option<HistorySort>,
+    /// Dates entries by the last visit rather than the work's last update.
+    #[serde(default)]
+    by_visit: bool,
+}
+
+impl HistoryQuery {
+    fn is_active(&self, facet: HistoryFacet) -> bool {
+        match facet {
+            HistoryFacet::Updated => self.updated,
+            HistoryFacet::MinVisits(visits) => self.visits == Some(visits),
+            HistoryFacet::VisitedWithin(within) => self.within == Some(within),
+            HistoryFacet::Sort(sort) => self.sort == Some(sort),
+            HistoryFacet::ByVisit => self.by_visit,
+        }
+    }
+
+    /// The query with the facet applied, or removed again if it already was.
+    fn toggle(&self, facet: HistoryFacet) -> Self {
+        let active = self.is_active(facet);
+        let mut query = self.clone();
+        match facet {
+            HistoryFacet::Updated => query.updated = !active,
+            HistoryFacet::MinVisits(visits) => query.visits = (!active).then_some(visits),
+            HistoryFacet::VisitedWithin(within) => query.within = (!active).then_some(within),
+            HistoryFacet::Sort(sort) => query.sort = (!active).then_some(sort),
+            HistoryFacet::ByVisit => query.by_visit = !active,
+        }
+        query
+    }
+
+    fn to_query_string(&self) -> String {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        if self.updated {
+            serializer.append_pair("updated", "true");
+        }
+        if let Some(visits) = self.visits {
+            serializer.append_pair("visits", &visits.to_string());
+        }
+        if let Some(within) = self.within {
+            serializer.append_pair("within", within.as_str());
+        }
+        if let Some(sort) = self.sort {
+            serializer.append_pair("sort", sort.as_str());
+        }
+        if self.by_visit {
+            serializer.append_pair("by_visit", "true");
+        }
+        serializer.finish()
+    }
+
+    /// The path of the readings feed with this query, relative to the feed root.
+    fn postfix(&self, readings: Readings) -> String {
+        let query = self.to_query_string();
+        if query.is_empty() {
+            readings.as_str().to_string()
+        } else {
+            format!("{}?{}", readings.as_str(), query)
+        }
+    }
+
+    fn allows(&self, work: &HistoryWork, now: DateTime<Utc>) -> bool {
+        (!self.updated || work.changed == Changed::Updated)
+            && self.visits.is_none_or(|visits| work.visited >= visits)
+            && self
+                .within
+                .is_none_or(|within| work.last_visited >= now - within.duration())
+    }
+
+    fn apply<'a>(&self, mut works: Vec<&'a HistoryWork>) -> Vec<&'a HistoryWork> {
+        let now = Utc::now();
+        works.retain(|work| self.allows(work, now));
+        match self.sort {
+            Some(HistorySort::Visited) => works.sort_by_key(|work| Reverse(work.last_visited)),
+            Some(HistorySort::Title) => works.sort_by_key(|work| work.work.title().to_lowercase()),
+            Some(HistorySort::Author) => works.sort_by_key(|work| work.work.authors().sort_key()),
+            None => {}
+        }
+        works
+    }
+}
+
+impl From<(Arc<HistoryPage>, &HistoryQuery, &Filters<'_>)> for OpdsFeed {
+    fn from((value, query, filters): (Arc<HistoryPage>, &HistoryQuery, &Filters<'_>)) -> Self {
+        let (history, hidden) = filters.apply(&value.history, |h| &h.work);
+        let readings = value.readings;
+        let mut feed = OpdsFeed::paginated(
+            &format!(
+                "{}-{}-page-{}",
+                readings.as_str(),
+                query.to_query_string(),
+                value.page
+            ),
+            &format!("{} page {}", readings.title(), value.page),
+            &query.postfix(readings),
+            query
+                .apply(history)
+                .into_iter()
+                .map(|work| work.entry(query.by_visit))
+                .collect(),
             value.page,
             value.has_next,
             value.has_prev,
+        );
+        feed.links
+            .get_or_insert_with(Vec::new)
+            .extend(FACETS.iter().map(|(group, title, facet)| {
+                OpdsLink::new(
+                    OpdsLinkType::Acquisition,
+                    OpdsLinkRel::Facet,
+                    format!("/opds/v1.2/{}", query.toggle(*facet).postfix(readings)),
+                )
+                .with_title(title)
+                .with_facet(group, query.is_active(*facet), None)
+            }));
+        feed.with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::{
+        Changed, HistoryFacet, HistoryPage, HistoryQuery, HistorySort, HistoryWork, Readings,
+    };
+    use crate::{ao3::work::tests::blurb, error::Error};
+
+    fn history_work(visits: &str) -> color_eyre::Result<HistoryWork> {
+        let html = Html::parse_fragment(&blurb(
+            "reading work",
+            1,
+            "03 Oct 2026",
+            "1/1",
+            &format!(
+                "<div class=\"user module group\"><h4 class=\"viewed heading\">{}</h4></div>",
+                visits
+            ),
+        ));
+        HistoryWork::from_element(&html.root_element())
+    }
+
+    #[test]
+    fn parses_visits() {
+        let work = history_work(
+            "<span>Last visited:</span> 01 Oct 2026\n\n  (Update available.)\n\n  Visited 12 times\n",
+        )
+        .unwrap();
+        assert_eq!(work.last_visited.to_rfc3339(), "2026-10-01T00:00:00+00:00");
+        assert_eq!(work.changed, Changed::Updated);
+        assert_eq!(work.visited, 12);
+
+        let work = history_work(
+            "<span>Last visited:</span> 01 Oct 2026\n\n  (Minor edits made since then.)\n\n  Visited once\n",
         )
+        .unwrap();
+        assert_eq!(work.changed, Changed::Minor);
+        assert_eq!(work.visited, 1);
+    }
+
+    #[test]
+    fn reports_unparsable_visits() {
+        for visits in [
+            "<span>Last visited:</span> 01 Oct 2026",
+            "<span>Last visited:</span> 01 Oct 2026\n\n  (Latest version.)\n\n  Visited many times\n",
+        ] {
+            let report = history_work(visits).unwrap_err();
+            assert!(matches!(
+                report.downcast_ref::<Error>(),
+                Some(Error::Parse { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn finds_the_last_page() {
+        let html = Html::parse_fragment(
+            r#"<ol class="pagination actions" role="navigation">
+<li class="previous"><span class="disabled">← Previous</span></li>
+<li><span class="current">1</span></li>
+<li><a href="/users/me/readings?page=2">2</a></li>
+<li class="gap">…</li>
+<li><a href="/users/me/readings?page=37">37</a></li>
+<li class="next"><a rel="next" href="/users/me/readings?page=2">Next →</a></li>
+</ol>"#,
+        );
+        let page = HistoryPage::from_element(&html.root_element(), Readings::History, 1).unwrap();
+        assert_eq!(page.last_page(), 37);
+        assert!(page.has_next);
+
+        let html = Html::parse_fragment(r#"<ol class="reading index"></ol>"#);
+        let page = HistoryPage::from_element(&html.root_element(), Readings::History, 1).unwrap();
+        assert_eq!(page.last_page(), 1);
+    }
+
+    #[test]
+    fn toggles_facets_in_postfix() {
+        let query = HistoryQuery::default().toggle(HistoryFacet::Updated);
+        let query = query.toggle(HistoryFacet::Sort(HistorySort::Title));
+        assert_eq!(
+            query.postfix(Readings::History),
+            "history?updated=true&sort=title"
+        );
+        assert!(query.is_active(HistoryFacet::Updated));
+        let query = query
+            .toggle(HistoryFacet::Updated)
+            .toggle(HistoryFacet::Sort(HistorySort::Title));
+        assert_eq!(query.postfix(Readings::Later), "later");
     }
 }
@@ -0,0 +1,505 @@
+use std::str::FromStr;
+
+use color_eyre::{eyre::eyre, Report, Result};
+use url::form_urlencoded;
+
+use super::required_tags::{Category, Rating, Warning};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SortColumn {
+    Relevance,
+    Author,
+    Title,
+    Posted,
+    Updated,
+    Words,
+    Hits,
+    Kudos,
+    Comments,
+    Bookmarks,
+}
+
+impl SortColumn {
+    pub(crate) const ALL: [SortColumn; 10] = [
+        Self::Relevance,
+        Self::Author,
+        Self::Title,
+        Self::Posted,
+        Self::Updated,
+        Self::Words,
+        Self::Hits,
+        Self::Kudos,
+        Self::Comments,
+        Self::Bookmarks,
+    ];
+
+    pub(crate) fn short_name(&self) -> &'static str {
+        match self {
+            SortColumn::Relevance => "relevance",
+            SortColumn::Author => "author",
+            SortColumn::Title => "title",
+            SortColumn::Posted => "posted",
+            SortColumn::Updated => "updated",
+            SortColumn::Words => "words",
+            SortColumn::Hits => "hits",
+            SortColumn::Kudos => "kudos",
+            SortColumn::Comments => "comments",
+            SortColumn::Bookmarks => "bookmarks",
+        }
+    }
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            SortColumn::Relevance => "Best Match",
+            SortColumn::Author => "Creator",
+            SortColumn::Title => "Title",
+            SortColumn::Posted => "Date Posted",
+            SortColumn::Updated => "Date Updated",
+            SortColumn::Words => "Word Count",
+            SortColumn::Hits => "Hits",
+            SortColumn::Kudos => "Kudos",
+            SortColumn::Comments => "Comments",
+            SortColumn::Bookmarks => "Bookmarks",
+        }
+    }
+
+    /// The value of AO3's `work_search[sort_column]`.
+    fn ao3_name(&self) -> &'static str {
+        match self {
+            SortColumn::Relevance => "_score",
+            SortColumn::Author => "authors_to_sort_on",
+            SortColumn::Title => "title_to_sort_on",
+            SortColumn::Posted => "created_at",
+            SortColumn::Updated => "revised_at",
+            SortColumn::Words => "word_count",
+            SortColumn::Hits => "hits",
+            SortColumn::Kudos => "kudos_count",
+            SortColumn::Comments => "comments_count",
+            SortColumn::Bookmarks => "bookmarks_count",
+        }
+    }
+}
+
+impl FromStr for SortColumn {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .find(|column| column.short_name() == s)
+            .copied()
+            .ok_or_else(|| eyre!("unknown sort column: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    fn short_name(&self) -> &'static str {
+        match self {
+            SortDirection::Ascending => "asc",
+            SortDirection::Descending => "desc",
+        }
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "asc" => Ok(SortDirection::Ascending),
+            "desc" => Ok(SortDirection::Descending),
+            other => Err(eyre!("unknown sort direction: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Crossovers {
+    Exclude,
+    Only,
+}
+
+/// A single option of AO3's filter sidebar, which can be toggled on a [`WorkSearchQuery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SearchFacet {
+    Rating(Rating),
+    Warning(Warning),
+    Category(Category),
+    /// Any other tag to include, by name.
+    Include(String),
+    /// Any tag to exclude, by name.
+    Exclude(String),
+    Sort(SortColumn),
+}
+
+/// A search on AO3's work search form.
+///
+/// Round trips through our own query strings with [`WorkSearchQuery::to_query_string`] and
+/// [`WorkSearchQuery::from_query_string`], and is sent to AO3 with [`WorkSearchQuery::ao3_pairs`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct WorkSearchQuery {
+    query: String,
+    fandoms: Vec<String>,
+    rating: Option<Rating>,
+    warnings: Vec<Warning>,
+    categories: Vec<Category>,
+    complete: Option<bool>,
+    words_from: Option<u32>,
+    words_to: Option<u32>,
+    language: Option<String>,
+    crossovers: Option<Crossovers>,
+    include_tags: Vec<String>,
+    exclude_tags: Vec<String>,
+    sort_column: Option<SortColumn>,
+    sort_direction: Option<SortDirection>,
+}
+
+impl WorkSearchQuery {
+    pub(crate) fn new(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn fandom(mut self, fandom: &str) -> Self {
+        self.fandoms.push(fandom.to_string());
+        self
+    }
+
+    pub(crate) fn rating(mut self, rating: Rating) -> Self {
+        self.rating = Some(rating);
+        self
+    }
+
+    pub(crate) fn warning(mut self, warning: Warning) -> Self {
+        self.warnings.push(warning);
+        self
+    }
+
+    pub(crate) fn category(mut self, category: Category) -> Self {
+        self.categories.push(category);
+        self
+    }
+
+    /// Only complete works with `true`, only works in progress with `false`.
+    pub(crate) fn complete(mut self, complete: bool) -> Self {
+        self.complete = Some(complete);
+        self
+    }
+
+    pub(crate) fn words(mut self, from: Option<u32>, to: Option<u32>) -> Self {
+        self.words_from = from;
+        self.words_to = to;
+        self
+    }
+
+    /// An AO3 language code, like `en`.
+    pub(crate) fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    pub(crate) fn crossovers(mut self, crossovers: Crossovers) -> Self {
+        self.crossovers = Some(crossovers);
+        self
+    }
+
+    /// Only works with this tag, in addition to any fandoms.
+    pub(crate) fn include_tag(mut self, tag: &str) -> Self {
+        self.include_tags.push(tag.to_string());
+        self
+    }
+
+    pub(crate) fn exclude_tag(mut self, tag: &str) -> Self {
+        self.exclude_tags.push(tag.to_string());
+        self
+    }
+
+    pub(crate) fn sort(mut self, column: SortColumn, direction: SortDirection) -> Self {
+        self.sort_column = Some(column);
+        self.sort_direction = Some(direction);
+        self
+    }
+
+    pub(crate) fn is_active(&self, facet: &SearchFacet) -> bool {
+        match facet {
+            SearchFacet::Rating(rating) => self.rating == Some(*rating),
+            SearchFacet::Warning(warning) => self.warnings.contains(warning),
+            SearchFacet::Category(category) => self.categories.contains(category),
+            SearchFacet::Include(tag) => self.include_tags.contains(tag),
+            SearchFacet::Exclude(tag) => self.exclude_tags.contains(tag),
+            SearchFacet::Sort(column) => self.sort_column == Some(*column),
+        }
+    }
+
+    /// The query with the facet applied, or removed again if it already was.
+    pub(crate) fn toggle(&self, facet: &SearchFacet) -> Self {
+        fn toggle_vec<T: PartialEq + Clone>(vec: &mut Vec<T>, value: &T) {
+            match vec.iter().position(|v| v == value) {
+                Some(index) => {
+                    vec.remove(index);
+                }
+                None => vec.push(value.clone()),
+            }
+        }
+
+        let active = self.is_active(facet);
+        let mut query = self.clone();
+        match facet {
+            SearchFacet::Rating(rating) => query.rating = (!active).then_some(*rating),
+            SearchFacet::Warning(warning) => toggle_vec(&mut query.warnings, warning),
+            SearchFacet::Category(category) => toggle_vec(&mut query.categories, category),
+            SearchFacet::Include(tag) => toggle_vec(&mut query.include_tags, tag),
+            SearchFacet::Exclude(tag) => toggle_vec(&mut query.exclude_tags, tag),
+            SearchFacet::Sort(column) => {
+                query.sort_column = (!active).then_some(*column);
+                query.sort_direction = (!active).then_some(SortDirection::Descending);
+            }
+        }
+        query
+    }
+
+    /// A short human readable description, used as the feed title.
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.query.is_empty() {
+            parts.push(format!("\"{}\"", self.query));
+        }
+        parts.extend(self.fandoms.iter().cloned());
+        parts.extend(self.include_tags.iter().cloned());
+        parts.extend(self.exclude_tags.iter().map(|tag| format!("-{}", tag)));
+        if let Some(rating) = self.rating {
+            parts.push(rating.to_string());
+        }
+        parts.extend(self.warnings.iter().map(Warning::to_string));
+        parts.extend(self.categories.iter().map(Category::to_string));
+        match self.complete {
+            Some(true) => parts.push("Complete".to_string()),
+            Some(false) => parts.push("Work in Progress".to_string()),
+            None => {}
+        }
+        parts.join(", ")
+    }
+
+    /// Our own query string representation, as used in OPDS links.
+    pub(crate) fn to_query_string(&self) -> String {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        if !self.query.is_empty() {
+            serializer.append_pair("q", &self.query);
+        }
+        for fandom in &self.fandoms {
+            serializer.append_pair("fandom", fandom);
+        }
+        if let Some(rating) = self.rating {
+            serializer.append_pair("rating", rating.short_name());
+        }
+        for warning in &self.warnings {
+            serializer.append_pair("warning", warning.short_name());
+        }
+        for category in &self.categories {
+            serializer.append_pair("category", category.short_name());
+        }
+        if let Some(complete) = self.complete {
+            serializer.append_pair("complete", if complete { "true" } else { "false" });
+        }
+        if let Some(from) = self.words_from {
+            serializer.append_pair("words_from", &from.to_string());
+        }
+        if let Some(to) = self.words_to {
+            serializer.append_pair("words_to", &to.to_string());
+        }
+        if let Some(language) = &self.language {
+            serializer.append_pair("language", language);
+        }
+        match self.crossovers {
+            Some(Crossovers::Exclude) => {
+                serializer.append_pair("crossovers", "exclude");
+            }
+            Some(Crossovers::Only) => {
+                serializer.append_pair("crossovers", "only");
+            }
+            None => {}
+        }
+        for tag in &self.include_tags {
+            serializer.append_pair("tag", tag);
+        }
+        for tag in &self.exclude_tags {
+            serializer.append_pair("without", tag);
+        }
+        if let Some(column) = self.sort_column {
+            serializer.append_pair("sort", column.short_name());
+        }
+        if let Some(direction) = self.sort_direction {
+            serializer.append_pair("direction", direction.short_name());
+        }
+        serializer.finish()
+    }
+
+    /// Parses our own query string representation, ignoring unrelated keys like `page`.
+    /// Goes through the builder, so a bookmarked search means the same as one built in code.
+    pub(crate) fn from_query_string(query: &str) -> Result<Self> {
+        let pairs: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+        let text = pairs
+            .iter()
+            .find(|(key, _)| key == "q")
+            .map(|(_, value)| value.as_ref())
+            .unwrap_or_default();
+        let mut search = WorkSearchQuery::new(text);
+        let mut sort = None;
+        let mut direction = None;
+        for (key, value) in &pairs {
+            search = match key.as_ref() {
+                "fandom" => search.fandom(value),
+                "rating" => search.rating(value.parse()?),
+                "warning" => search.warning(value.parse()?),
+                "category" => search.category(value.parse()?),
+                "complete" => search.complete(value.parse()?),
+                "words_from" => {
+                    let to = search.words_to;
+                    search.words(Some(value.parse()?), to)
+                }
+                "words_to" => {
+                    let from = search.words_from;
+                    search.words(from, Some(value.parse()?))
+                }
+                "language" => search.language(value),
+                "crossovers" => search.crossovers(match value.as_ref() {
+                    "exclude" => Crossovers::Exclude,
+                    "only" => Crossovers::Only,
+                    other => return Err(eyre!("unknown crossovers option: {}", other)),
+                }),
+                "tag" => search.include_tag(value),
+                "without" => search.exclude_tag(value),
+                "sort" => {
+                    sort = Some(value.parse()?);
+                    search
+                }
+                "direction" => {
+                    direction = Some(value.parse()?);
+                    search
+                }
+                _ => search,
+            };
+        }
+        if let Some(column) = sort {
+            // AO3 sorts descending unless told otherwise
+            search = search.sort(column, direction.unwrap_or(SortDirection::Descending));
+        }
+        Ok(search)
+    }
+
+    /// The `work_search[...]` fields of AO3's search form.
+    pub(crate) fn ao3_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![("work_search[query]", self.query.clone())];
+        if !self.fandoms.is_empty() {
+            pairs.push(("work_search[fandom_names]", self.fandoms.join(",")));
+        }
+        if let Some(rating) = self.rating {
+            pairs.push(("work_search[rating_ids]", rating.id().to_string()));
+        }
+        for warning in &self.warnings {
+            pairs.push((
+                "work_search[archive_warning_ids][]",
+                warning.id().to_string(),
+            ));
+        }
+        for category in &self.categories {
+            pairs.push(("work_search[category_ids][]", category.id().to_string()));
+        }
+        if let Some(complete) = self.complete {
+            pairs.push((
+                "work_search[complete]",
+                if complete { "T" } else { "F" }.to_string(),
+            ));
+        }
+        let word_count = match (self.words_from, self.words_to) {
+            (Some(from), Some(to)) => Some(format!("{}-{}", from, to)),
+            (Some(from), None) => Some(format!(">{}", from)),
+            (None, Some(to)) => Some(format!("<{}", to)),
+            (None, None) => None,
+        };
+        if let Some(word_count) = word_count {
+            pairs.push(("work_search[word_count]", word_count));
+        }
+        if let Some(language) = &self.language {
+            pairs.push(("work_search[language_id]", language.clone()));
+        }
+        match self.crossovers {
+            Some(Crossovers::Exclude) => pairs.push(("work_search[crossover]", "F".to_string())),
+            Some(Crossovers::Only) => pairs.push(("work_search[crossover]", "T".to_string())),
+            None => {}
+        }
+        if !self.include_tags.is_empty() {
+            pairs.push(("work_search[other_tag_names]", self.include_tags.join(",")));
+        }
+        if !self.exclude_tags.is_empty() {
+            pairs.push((
+                "work_search[excluded_tag_names]",
+                self.exclude_tags.join(","),
+            ));
+        }
+        if let Some(column) = self.sort_column {
+            pairs.push(("work_search[sort_column]", column.ao3_name().to_string()));
+        }
+        if let Some(direction) = self.sort_direction {
+            pairs.push((
+                "work_search[sort_direction]",
+                direction.short_name().to_string(),
+            ));
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Crossovers, SearchFacet, SortColumn, SortDirection, WorkSearchQuery};
+    use crate::ao3::required_tags::{Category, Rating, Warning};
+
+    #[test]
+    fn query_string_round_trip() {
+        let query = WorkSearchQuery::new("coffee shop")
+            .fandom("Good Omens (TV)")
+            .rating(Rating::Teen)
+            .warning(Warning::NoneApply)
+            .category(Category::MM)
+            .category(Category::Gen)
+            .complete(true)
+            .words(Some(1000), None)
+            .language("en")
+            .crossovers(Crossovers::Exclude)
+            .include_tag("Fluff")
+            .exclude_tag("Angst")
+            .sort(SortColumn::Kudos, SortDirection::Descending);
+
+        let parsed = WorkSearchQuery::from_query_string(&query.to_query_string()).unwrap();
+        assert_eq!(parsed, query);
+    }
+
+    #[test]
+    fn maps_to_ao3_fields() {
+        let pairs = WorkSearchQuery::new("")
+            .rating(Rating::Explicit)
+            .words(Some(1000), Some(5000))
+            .ao3_pairs();
+        assert!(pairs.contains(&("work_search[rating_ids]", "13".to_string())));
+        assert!(pairs.contains(&("work_search[word_count]", "1000-5000".to_string())));
+    }
+
+    #[test]
+    fn toggles_facets() {
+        let query = WorkSearchQuery::new("");
+        let facet = SearchFacet::Include("Fluff".to_string());
+        let toggled = query.toggle(&facet);
+        assert!(toggled.is_active(&facet));
+        assert_eq!(toggled.to_query_string(), "tag=Fluff");
+        assert_eq!(toggled.toggle(&facet), query);
+    }
+}
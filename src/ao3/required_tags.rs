@@ -0,0 +1,219 @@
+use std::{fmt, str::FromStr};
+
+use color_eyre::{eyre::eyre, Report};
+use scraper::ElementRef;
+use serde::Deserialize;
+
+use super::{utils::*, work::tag_scheme};
+use crate::opds::OpdsCategory;
+
+/// Generates the conversions between a required tag and its AO3 tag id, short name and label.
+///
+/// The short name is what we use in our own query strings, `$kind` is the tag kind in category schemes.
+macro_rules! required_tag {
+    ($name:ident, $kind:expr, { $($variant:ident => ($id:expr, $short:expr, $label:expr)),+ $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+        #[serde(try_from = "String")]
+        pub(crate) enum $name {
+            $($variant),+
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = Report;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                value.parse()
+            }
+        }
+
+        impl $name {
+            pub(crate) const ALL: &'static [$name] = &[$($name::$variant),+];
+
+            /// The AO3 tag id, as used in the work search form.
+            pub(crate) fn id(&self) -> u32 {
+                match self {
+                    $($name::$variant => $id),+
+                }
+            }
+
+            pub(crate) fn short_name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $short),+
+                }
+            }
+
+            /// The name of the tag on AO3.
+            pub(crate) fn label(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $label),+
+                }
+            }
+
+            pub(crate) fn from_label(label: &str) -> Option<Self> {
+                $name::ALL.iter().find(|tag| tag.label() == label).copied()
+            }
+
+            pub(crate) fn category(&self) -> OpdsCategory {
+                OpdsCategory::new(
+                    tag_scheme($kind),
+                    self.label().to_string(),
+                    Some(self.label().to_string()),
+                )
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Report;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::ALL
+                    .iter()
+                    .find(|tag| tag.short_name() == s)
+                    .copied()
+                    .ok_or_else(|| eyre!("unknown {}: {}", stringify!($name), s))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.label())
+            }
+        }
+    };
+}
+
+required_tag!(Rating, "rating", {
+    NotRated => (9, "not-rated", "Not Rated"),
+    General => (10, "general", "General Audiences"),
+    Teen => (11, "teen", "Teen And Up Audiences"),
+    Mature => (12, "mature", "Mature"),
+    Explicit => (13, "explicit", "Explicit"),
+});
+
+required_tag!(Warning, "warning", {
+    ChoseNotToUse => (14, "choose-not", "Creator Chose Not To Use Archive Warnings"),
+    NoneApply => (16, "none", "No Archive Warnings Apply"),
+    Violence => (17, "violence", "Graphic Depictions Of Violence"),
+    MajorCharacterDeath => (18, "death", "Major Character Death"),
+    NonCon => (19, "noncon", "Rape/Non-Con"),
+    Underage => (20, "underage", "Underage Sex"),
+});
+
+required_tag!(Category, "category", {
+    FF => (116, "ff", "F/F"),
+    FM => (22, "fm", "F/M"),
+    Gen => (21, "gen", "Gen"),
+    MM => (23, "mm", "M/M"),
+    Multi => (2246, "multi", "Multi"),
+    Other => (24, "other", "Other"),
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Completion {
+    Complete,
+    #[serde(rename = "wip")]
+    InProgress,
+}
+
+impl Completion {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Completion::Complete => "Complete Work",
+            Completion::InProgress => "Work in Progress",
+        }
+    }
+
+    pub(crate) fn category(&self) -> OpdsCategory {
+        OpdsCategory::new(
+            tag_scheme("status"),
+            self.label().to_string(),
+            Some(self.label().to_string()),
+        )
+    }
+}
+
+/// The square of symbols in the corner of a blurb.
+#[derive(Debug, Clone)]
+pub(crate) struct RequiredTags {
+    pub(crate) rating: Option<Rating>,
+    pub(crate) warnings: Vec<Warning>,
+    pub(crate) categories: Vec<Category>,
+    pub(crate) completion: Completion,
+}
+
+impl RequiredTags {
+    pub(crate) fn from_element(element: &ElementRef) -> color_eyre::Result<Self> {
+        // the symbols carry their tags, comma separated, in the title
+        let titles = |selector| -> Vec<String> {
+            select_next_attr(element, selector, "title")
+                .map(|title| title.split(", ").map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+
+        let completion = match select_next(element, "span.complete-yes") {
+            Ok(_) => Completion::Complete,
+            Err(_) => Completion::InProgress,
+        };
+
+        Ok(RequiredTags {
+            rating: titles("span.rating")
+                .first()
+                .and_then(|r| Rating::from_label(r)),
+            warnings: titles("span.warnings")
+                .iter()
+                .filter_map(|w| Warning::from_label(w))
+                .collect(),
+            categories: titles("span.category")
+                .iter()
+                .filter_map(|c| Category::from_label(c))
+                .collect(),
+            completion,
+        })
+    }
+
+    pub(crate) fn categories(&self) -> Vec<OpdsCategory> {
+        let mut categories: Vec<_> = self.rating.iter().map(Rating::category).collect();
+        categories.extend(self.warnings.iter().map(Warning::category));
+        categories.extend(self.categories.iter().map(Category::category));
+        categories.push(self.completion.category());
+        categories
+    }
+}
+
+impl fmt::Display for RequiredTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![self.rating.map_or("No rating", |r| r.label())];
+        parts.extend(self.warnings.iter().map(Warning::label));
+        parts.extend(self.categories.iter().map(Category::label));
+        parts.push(self.completion.label());
+        f.write_str(&parts.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::{Category, Completion, Rating, RequiredTags, Warning};
+
+    #[test]
+    fn parses_required_tags() {
+        let html = Html::parse_fragment(
+            r#"<ul class="required-tags">
+<li><a><span class="rating-explicit rating" title="Explicit"><span class="text">Explicit</span></span></a></li>
+<li><a><span class="warning-yes warnings" title="Graphic Depictions Of Violence, Major Character Death"><span class="text">...</span></span></a></li>
+<li><a><span class="category-multi category" title="F/M, M/M"><span class="text">F/M, M/M</span></span></a></li>
+<li><a><span class="complete-no iswip" title="Work in Progress"><span class="text">Work in Progress</span></span></a></li>
+</ul>"#,
+        );
+        let tags = RequiredTags::from_element(&html.root_element()).unwrap();
+        assert_eq!(tags.rating, Some(Rating::Explicit));
+        assert_eq!(
+            tags.warnings,
+            [Warning::Violence, Warning::MajorCharacterDeath]
+        );
+        assert_eq!(tags.categories, [Category::FM, Category::MM]);
+        assert_eq!(tags.completion, Completion::InProgress);
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use color_eyre::Result;
+use scraper::ElementRef;
+
+use crate::opds::OpdsFeed;
+
+use super::{session::AuthorizedSession, utils::*, Filters, Work, WorkSearchQuery};
+
+#[derive(Debug, Clone)]
+pub(crate) struct SearchPage {
+    works: Vec<Work>,
+    query: WorkSearchQuery,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl SearchPage {
+    pub(crate) fn from_element(
+        element: &ElementRef,
+        query: &WorkSearchQuery,
+        page: usize,
+    ) -> Result<SearchPage> {
+        let mut works = Vec::new();
+
+        for element in select_all(element, "ol.work.index > li.work.blurb") {
+            works.push(Work::from_element(&element)?);
+        }
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(SearchPage {
+            works,
+            query: query.clone(),
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) fn works(&self) -> &[Work] {
+        &self.works
+    }
+
+    pub(crate) async fn new(
+        session: &AuthorizedSession,
+        query: &WorkSearchQuery,
+        page: usize,
+    ) -> Result<SearchPage> {
+        let html = session.get_search_page(query, page).await?;
+        Self::from_element(&html.root_element(), query, page)
+    }
+}
+
+impl From<(Arc<SearchPage>, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (Arc<SearchPage>, &Filters<'_>)) -> Self {
+        let (works, hidden) = filters.apply(&value.works, |w| w);
+        let query = value.query.to_query_string();
+        OpdsFeed::paginated(
+            &format!("search-{}-page-{}", query, value.page),
+            &format!(
+                "Search results for {} page {}",
+                value.query.describe(),
+                value.page
+            ),
+            &format!("search?{}", query),
+            works,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        )
+        .with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use scraper::Html;
+
+    use super::SearchPage;
+    use crate::{
+        ao3::{work::tests::blurb, FilterConfig, WorkSearchQuery},
+        opds::OpdsFeed,
+    };
+
+    const PAGINATION: &str = r#"<ol class="pagination actions" role="navigation">
+<li class="previous"><a rel="prev" href="/works/search?page=1">← Previous</a></li>
+<li><a href="/works/search?page=1">1</a></li>
+<li><span class="current">2</span></li>
+<li><a href="/works/search?page=3">3</a></li>
+<li class="next"><a rel="next" href="/works/search?page=3">Next →</a></li>
+</ol>"#;
+
+    #[test]
+    fn parses_search_results() {
+        let html = Html::parse_document(&format!(
+            r#"<html><body><h3 class="heading">41 - 80 of 100 Works found</h3>
+<ol class="work index group">{}{}</ol>{}</body></html>"#,
+            blurb("work", 1, "03 Oct 2026", "1/1", ""),
+            blurb("work", 2, "02 Oct 2026", "3/?", ""),
+            PAGINATION
+        ));
+        let query = WorkSearchQuery::new("coffee shop");
+        let page = SearchPage::from_element(&html.root_element(), &query, 2).unwrap();
+
+        let ids: Vec<_> = page.works().iter().map(|w| w.id()).collect();
+        assert_eq!(ids, [1, 2]);
+        assert!(page.has_prev);
+        assert!(page.has_next);
+
+        let config = FilterConfig::default();
+        let feed = OpdsFeed::from((Arc::new(page), &config.filters(None, "search")));
+        assert_eq!(feed.entries.len(), 2);
+        assert_eq!(feed.title, "Search results for \"coffee shop\" page 2");
+        let hrefs: Vec<_> = feed
+            .links
+            .unwrap()
+            .into_iter()
+            .filter(|link| link.rel == "previous" || link.rel == "next")
+            .map(|link| link.href)
+            .collect();
+        assert_eq!(
+            hrefs,
+            [
+                "/opds/v1.2/search?q=coffee+shop&page=1",
+                "/opds/v1.2/search?q=coffee+shop&page=3"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_empty_results() {
+        let html = Html::parse_document(
+            r#"<html><body><h3 class="heading">0 Found</h3>
+<p>No results found. You may want to edit your search to make it less specific.</p>
+</body></html>"#,
+        );
+        let query = WorkSearchQuery::new("nothing like this");
+        let page = SearchPage::from_element(&html.root_element(), &query, 1).unwrap();
+
+        assert!(page.works().is_empty());
+        assert!(!page.has_prev);
+        assert!(!page.has_next);
+        let config = FilterConfig::default();
+        let feed = OpdsFeed::from((Arc::new(page), &config.filters(None, "search")));
+        assert!(feed.entries.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use color_eyre::Result;
+use scraper::ElementRef;
+
+use crate::opds::OpdsFeed;
+
+use super::{session::AuthorizedSession, utils::*, work::Authors, Filters, Work};
+
+#[derive(Debug, Clone)]
+pub(crate) struct SeriesPage {
+    id: i64,
+    title: String,
+    creators: Authors,
+    description: Option<String>,
+    notes: Option<String>,
+    complete: bool,
+    words: i32,
+    /// The works on this page, in part order.
+    works: Vec<Work>,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl SeriesPage {
+    pub(crate) fn from_element(element: &ElementRef, id: i64, page: usize) -> Result<SeriesPage> {
+        let title = select_string(element, "h2.heading")?.trim().to_string();
+
+        let mut creators = None;
+        let mut description = None;
+        let mut notes = None;
+        // the metadata is a list of dt/dd pairs without classes
+        let meta = select_next(element, "dl.series.meta")?;
+        let names = select_all(&meta, "dl.series.meta > dt");
+        let values = select_all(&meta, "dl.series.meta > dd");
+        for (name, value) in names.iter().zip(values.iter()) {
+            let text = || value.text().collect::<String>().trim().to_string();
+            match name.text().collect::<String>().trim() {
+                "Creator:" | "Creators:" => creators = Some(Authors::from_element(value)?),
+                "Description:" => description = Some(text()),
+                "Notes:" => notes = Some(text()),
+                _ => {}
+            }
+        }
+
+        let stats = select_next(&meta, "dl.stats")?;
+        let stat_names = select_all(&stats, "dt");
+        let stat_values = select_all(&stats, "dd");
+        let complete = stat_names
+            .iter()
+            .zip(stat_values.iter())
+            .find(|(name, _)| name.text().collect::<String>().trim() == "Complete:")
+            .is_some_and(|(_, value)| value.text().collect::<String>().trim() == "Yes");
+
+        let mut works = Vec::new();
+        for element in select_all(element, "ul.series.work.index > li.work.blurb") {
+            works.push(Work::from_element(&element)?);
+        }
+        works.sort_by_key(|work| work.series_part(id));
+
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(SeriesPage {
+            id,
+            title,
+            creators: creators.unwrap_or_default(),
+            description,
+            notes,
+            complete,
+            words: select_int(&stats, "dd.words").unwrap_or(0),
+            works,
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) fn works(&self) -> &[Work] {
+        &self.works
+    }
+
+    pub(crate) async fn new(
+        session: &AuthorizedSession,
+        id: i64,
+        page: usize,
+    ) -> Result<SeriesPage> {
+        let html = session.get_series_page(id, page).await?;
+        Self::from_element(&html.root_element(), id, page)
+    }
+
+    /// A short description of the series, shown as the feed subtitle.
+    fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "By {}, {} words, {}",
+            self.creators,
+            self.words,
+            if self.complete {
+                "complete"
+            } else {
+                "in progress"
+            }
+        )];
+        lines.extend(self.description.clone());
+        lines.extend(self.notes.as_ref().map(|notes| format!("Notes: {}", notes)));
+        lines.join("\n")
+    }
+}
+
+impl From<(Arc<SeriesPage>, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (Arc<SeriesPage>, &Filters<'_>)) -> Self {
+        let (works, hidden) = filters.apply(&value.works, |w| w);
+        let mut feed = OpdsFeed::paginated(
+            &format!("series-{}-page-{}", value.id, value.page),
+            &value.title,
+            &format!("series/{}", value.id),
+            works,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        );
+        feed.subtitle = Some(value.describe());
+        feed.with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::SeriesPage;
+    use crate::ao3::work::tests::blurb;
+
+    fn part(id: i64, part: i32) -> String {
+        blurb(
+            "work",
+            id,
+            "03 Oct 2026",
+            "1/1",
+            &format!(
+                r#"<ul class="series"><li>Part <strong>{}</strong> of <a href="/series/7">Bookshop</a></li></ul>"#,
+                part
+            ),
+        )
+    }
+
+    #[test]
+    fn parses_series_page() {
+        let html = Html::parse_document(&format!(
+            r#"<html><body>
+<h2 class="heading">Bookshop</h2>
+<dl class="series meta group">
+  <dt>Creator:</dt><dd><a rel="author" href="/users/someone/pseuds/someone">someone</a></dd>
+  <dt>Series Begun:</dt><dd>2023-01-02</dd>
+  <dt>Description:</dt><dd><blockquote class="userstuff"><p>Books, mostly.</p></blockquote></dd>
+  <dt>Notes:</dt><dd><blockquote class="userstuff"><p>Read in order.</p></blockquote></dd>
+  <dt>Stats:</dt><dd><dl class="stats">
+    <dt>Words:</dt><dd class="words">2,468</dd>
+    <dt>Works:</dt><dd>2</dd>
+    <dt>Complete:</dt><dd>Yes</dd>
+  </dl></dd>
+</dl>
+<ul class="series work index group">{}{}</ul>
+</body></html>"#,
+            part(2, 2),
+            part(1, 1)
+        ));
+        let page = SeriesPage::from_element(&html.root_element(), 7, 1).unwrap();
+
+        assert_eq!(page.title, "Bookshop");
+        assert_eq!(page.creators.to_string(), "someone");
+        assert_eq!(page.description.as_deref(), Some("Books, mostly."));
+        assert_eq!(page.notes.as_deref(), Some("Read in order."));
+        assert!(page.complete);
+        assert_eq!(page.words, 2468);
+        let ids: Vec<_> = page.works.iter().map(|w| w.id()).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(
+            page.describe(),
+            "By someone, 2468 words, complete\nBooks, mostly.\nNotes: Read in order."
+        );
+    }
+}
@@ -1,17 +1,108 @@
+use std::{path::PathBuf, sync::Arc};
+
 use color_eyre::{eyre::eyre, Result};
 use scraper::{Html, Selector};
 
-use reqwest::{Client, ClientBuilder, Url};
+use reqwest::{header, Client, ClientBuilder, Response, StatusCode, Url};
+use tokio::sync::Mutex;
+
+use super::{
+    cookies::CookieFile,
+    download::{download_filename, DownloadFormat},
+    utils::escape_tag,
+    SubscriptionKind, WorkSearchQuery,
+};
+use crate::error::Error;
+
+/// Turns AO3's error statuses into our errors.
+fn check_status(res: &Response) -> Result<()> {
+    match res.status() {
+        StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = res
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok());
+            Err(Error::RateLimited { retry_after }.into())
+        }
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+            Err(Error::Ao3Unavailable.into())
+        }
+        StatusCode::NOT_FOUND => {
+            let path = res.url().path();
+            let id = path
+                .strip_prefix("/works/")
+                .or_else(|| path.strip_prefix("/downloads/"))
+                .and_then(|id| id.split('/').next())
+                .and_then(|id| id.parse().ok());
+            Err(match id {
+                Some(id) => Error::WorkUnavailable(id).into(),
+                None => eyre!("AO3 page not found: {}", res.url()),
+            })
+        }
+        _ => Ok(()),
+    }
+}
 
 pub(crate) struct Session {
     client: Client,
+    cookies: CookieFile,
 }
 
 #[derive(Clone)]
 pub(crate) struct AuthorizedSession {
     client: Client,
+    cookies: CookieFile,
     username: String,
     password: String,
+    /// Counts the logins done after the session expired, and serialises them.
+    relogin: Arc<Mutex<u64>>,
+}
+
+/// AO3 marks the body of every page with whether the visitor is logged in.
+fn is_logged_in(html: &Html) -> bool {
+    let selector = Selector::parse("body.logged-in").unwrap();
+    html.select(&selector).next().is_some()
+}
+
+async fn get_authenticity_token(client: &Client) -> Result<String> {
+    let body = client
+        .get(Session::login_url())
+        .send()
+        .await?
+        .text()
+        .await?;
+    let doc = Html::parse_document(&body);
+    let selector = Selector::parse(r#"input[name="authenticity_token"]"#).unwrap();
+    Ok(match doc.select(&selector).next() {
+        Some(input) => input
+            .value()
+            .attr("value")
+            .ok_or(eyre!("Issue getting authenticity token"))?
+            .to_string(),
+        None => "".to_string(),
+    })
+}
+
+async fn login(client: &Client, username: &str, password: &str) -> Result<()> {
+    let authenticity_token = get_authenticity_token(client).await?;
+    let payload = [
+        ("user[login]", username),
+        ("user[password]", password),
+        ("user[remember_me]", "1"),
+        ("authenticity_token", &authenticity_token),
+    ];
+    let res = client
+        .post(Session::login_url())
+        .form(&payload)
+        .send()
+        .await?;
+    tracing::debug!(url = %res.url(), "logged in to AO3");
+    if res.url().as_str() != Session::login_url().as_str() {
+        Ok(())
+    } else {
+        Err(Error::NotLoggedIn("Invalid username or password".to_string()).into())
+    }
 }
 
 impl Session {
@@ -23,63 +114,51 @@ impl Session {
         url
     }
 
-    pub(crate) fn new() -> Result<Self> {
-        let client = ClientBuilder::new().cookie_store(true).build()?;
-        Ok(Self { client })
+    /// Creates a session which keeps its cookies in `cookie_file`.
+    pub(crate) fn new(cookie_file: PathBuf) -> Result<Self> {
+        let cookies = CookieFile::new(cookie_file, Url::parse(Self::BASE_URL).unwrap());
+        let client = ClientBuilder::new()
+            .cookie_provider(cookies.jar())
+            .build()?;
+        Ok(Self { client, cookies })
     }
 
-    pub(crate) async fn login(self, username: &str, password: &str) -> Result<AuthorizedSession> {
-        let authenticity_token = self.get_authenticiry_token().await?;
-        let payload = [
-            ("user[login]", username),
-            ("user[password]", password),
-            ("authenticity_token", &authenticity_token),
-        ];
-        let res = self
-            .client
-            .post(Session::login_url())
-            .form(&payload)
-            .send()
-            .await?;
-        dbg!(res.url());
-        if res.url().as_str() != Session::login_url().as_str() {
-            Ok(AuthorizedSession {
-                client: self.client,
-                username: username.to_string(),
-                password: password.to_string(),
-            })
-        } else {
-            // println!("{:?}", res.text().await?);
-            Err(eyre!("Invalid username or password"))
+    /// Reuses the cookies from a previous run if they are still logged in, logging in otherwise.
+    pub(crate) async fn resume(self, username: &str, password: &str) -> Result<AuthorizedSession> {
+        if self.cookies.load().await? && self.check_logged_in().await? {
+            self.cookies.save().await?;
+            return Ok(self.authorize(username, password));
         }
+        self.login(username, password).await
     }
 
-    async fn get_authenticiry_token(&self) -> Result<String, color_eyre::Report> {
-        let body = self
-            .client
-            .get(Session::login_url())
-            .send()
-            .await?
-            .text()
-            .await?;
-        let doc = Html::parse_document(&body);
-        let selector = Selector::parse(r#"input[name="authenticity_token"]"#).unwrap();
-        Ok(match doc.select(&selector).next() {
-            Some(input) => input
-                .value()
-                .attr("value")
-                .ok_or(eyre!("Issue getting authenticity token"))?
-                .to_string(),
-            None => "".to_string(),
-        })
+    async fn check_logged_in(&self) -> Result<bool> {
+        let body = self.client.get(Self::BASE_URL).send().await?.text().await?;
+        Ok(is_logged_in(&Html::parse_document(&body)))
+    }
+
+    pub(crate) async fn login(self, username: &str, password: &str) -> Result<AuthorizedSession> {
+        login(&self.client, username, password).await?;
+        self.cookies.save().await?;
+        Ok(self.authorize(username, password))
+    }
+
+    fn authorize(self, username: &str, password: &str) -> AuthorizedSession {
+        AuthorizedSession {
+            client: self.client,
+            cookies: self.cookies,
+            username: username.to_string(),
+            password: password.to_string(),
+            relogin: Arc::new(Mutex::new(0)),
+        }
     }
 }
 
 impl AuthorizedSession {
-    pub(crate) fn subscriptions_url(user: &str, page: usize) -> Url {
+    pub(crate) fn subscriptions_url(user: &str, kind: SubscriptionKind, page: usize) -> Url {
         let mut url = Url::parse(Session::BASE_URL).unwrap();
         url.set_path(&("/users/".to_string() + user + "/subscriptions"));
-        url.set_query(Some(&format!("page={}", page)));
+        url.set_query(Some(&format!("type={}&page={}", kind.as_str(), page)));
         url
     }
     pub(crate) fn bookmarks_url(user: &str, page: usize) -> Url {
@@ -96,14 +175,220 @@ impl AuthorizedSession {
         url
     }
 
+    /// The readings of a user which are marked for later.
+    pub(crate) fn later_url(user: &str, page: usize) -> Url {
+        let mut url = Self::history_url(user, page);
+        url.query_pairs_mut().append_pair("show", "to-read");
+        url
+    }
+
+    /// Marks a work for later, or as read again when `later` is false.
+    pub(crate) fn mark_for_later_url(id: i64, later: bool) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        if later {
+            url.set_path(&format!("/works/{}/mark_for_later", id));
+        } else {
+            url.set_path(&format!("/works/{}/mark_as_read", id));
+        }
+        url
+    }
+
+    pub(crate) fn download_url(id: i64, format: DownloadFormat) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        // AO3 ignores the file name, only the extension matters
+        url.set_path(&format!(
+            "/downloads/{}/{}",
+            id,
+            download_filename("", format)
+        ));
+        url
+    }
+
+    pub(crate) fn work_url(id: i64) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        url.set_path(&format!("/works/{}", id));
+        // skips the adult content interstitial
+        url.set_query(Some("view_adult=true"));
+        url
+    }
+
+    pub(crate) fn series_url(id: i64, page: usize) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        url.set_path(&format!("/series/{}", id));
+        url.set_query(Some(&format!("page={}", page)));
+        url
+    }
+
+    pub(crate) fn author_works_url(user: &str, pseud: Option<&str>, page: usize) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        match pseud {
+            Some(pseud) => url.set_path(&format!("/users/{}/pseuds/{}/works", user, pseud)),
+            None => url.set_path(&format!("/users/{}/works", user)),
+        }
+        url.set_query(Some(&format!("page={}", page)));
+        url
+    }
+
+    pub(crate) fn tag_works_url(tag: &str, query: &WorkSearchQuery, page: usize) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["tags", &escape_tag(tag), "works"]);
+        url.query_pairs_mut()
+            .extend_pairs(query.ao3_pairs())
+            .append_pair("page", &page.to_string());
+        url
+    }
+
+    pub(crate) fn search_url(query: &WorkSearchQuery, page: usize) -> Url {
+        let mut url = Url::parse(Session::BASE_URL).unwrap();
+        url.set_path("/works/search");
+        url.query_pairs_mut()
+            .extend_pairs(query.ao3_pairs())
+            .append_pair("page", &page.to_string());
+        url
+    }
+
+    /// Fetches a page, returning the url it ended up at after redirects with its body.
+    async fn fetch(&self, url: Url) -> Result<Option<(Url, String)>> {
+        let res = self.client.get(url).send().await?;
+        check_status(&res)?;
+        if res.url().path() == Session::login_url().path() {
+            return Ok(None);
+        }
+        let url = res.url().clone();
+        let body = res.text().await?;
+        Ok(is_logged_in(&Html::parse_document(&body)).then_some((url, body)))
+    }
+
+    /// Logs in again with the stored credentials, unless another request already did
+    /// since `generation`.
+    async fn relogin(&self, generation: u64) -> Result<()> {
+        let mut current = self.relogin.lock().await;
+        if *current == generation {
+            login(&self.client, &self.username, &self.password).await?;
+            self.cookies.save().await?;
+            *current += 1;
+        }
+        Ok(())
+    }
+
+    async fn get_html(&self, url: Url) -> Result<Html> {
+        Ok(self.get_html_with_url(url).await?.1)
+    }
+
+    /// Like [`AuthorizedSession::get_html`], but also returns the url after redirects.
+    async fn get_html_with_url(&self, url: Url) -> Result<(Url, Html)> {
+        let generation = *self.relogin.lock().await;
+        if let Some((url, body)) = self.fetch(url.clone()).await? {
+            return Ok((url, Html::parse_document(&body)));
+        }
+
+        self.relogin(generation).await?;
+        let (url, body) = self.fetch(url.clone()).await?.ok_or_else(|| {
+            Error::NotLoggedIn(format!("still logged out after logging in again: {}", url))
+        })?;
+        Ok((url, Html::parse_document(&body)))
+    }
+
     pub(crate) async fn get_history_page(&self, page: usize) -> Result<Html> {
-        let body = self
-            .client
-            .get(Self::history_url(&self.username, page))
-            .send()
-            .await?
-            .text()
-            .await?;
-        Ok(Html::parse_document(&body))
+        self.get_html(Self::history_url(&self.username, page)).await
+    }
+
+    pub(crate) async fn get_later_page(&self, page: usize) -> Result<Html> {
+        self.get_html(Self::later_url(&self.username, page)).await
+    }
+
+    pub(crate) async fn mark_for_later(&self, id: i64, later: bool) -> Result<()> {
+        self.get_html(Self::mark_for_later_url(id, later)).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn get_bookmarks_page(&self, page: usize) -> Result<Html> {
+        self.get_html(Self::bookmarks_url(&self.username, page))
+            .await
+    }
+
+    pub(crate) async fn get_subscriptions_page(
+        &self,
+        kind: SubscriptionKind,
+        page: usize,
+    ) -> Result<Html> {
+        self.get_html(Self::subscriptions_url(&self.username, kind, page))
+            .await
+    }
+
+    pub(crate) async fn get_search_page(
+        &self,
+        query: &WorkSearchQuery,
+        page: usize,
+    ) -> Result<Html> {
+        self.get_html(Self::search_url(query, page)).await
+    }
+
+    pub(crate) async fn get_work_page(&self, id: i64) -> Result<Html> {
+        self.get_html(Self::work_url(id)).await
+    }
+
+    pub(crate) async fn get_series_page(&self, id: i64, page: usize) -> Result<Html> {
+        self.get_html(Self::series_url(id, page)).await
+    }
+
+    pub(crate) async fn get_author_works_page(
+        &self,
+        user: &str,
+        pseud: Option<&str>,
+        page: usize,
+    ) -> Result<Html> {
+        self.get_html(Self::author_works_url(user, pseud, page))
+            .await
+    }
+
+    /// Fetches the works listing of a tag, returning the url AO3 redirected to with it.
+    pub(crate) async fn get_tag_works_page(
+        &self,
+        tag: &str,
+        query: &WorkSearchQuery,
+        page: usize,
+    ) -> Result<(Url, Html)> {
+        self.get_html_with_url(Self::tag_works_url(tag, query, page))
+            .await
+    }
+
+    /// Requests a download of the work, passing on a `Range` header, for streaming the response.
+    pub(crate) async fn get_download(
+        &self,
+        id: i64,
+        format: DownloadFormat,
+        range: Option<&str>,
+    ) -> Result<Response> {
+        let url = Self::download_url(id, format);
+        let request = || {
+            let request = self.client.get(url.clone());
+            match range {
+                Some(range) => request.header(header::RANGE, range),
+                None => request,
+            }
+        };
+
+        let generation = *self.relogin.lock().await;
+        let res = request().send().await?;
+        check_status(&res)?;
+        if res.url().path() != Session::login_url().path() {
+            return Ok(res);
+        }
+
+        // restricted works redirect to the login page when the session expired
+        self.relogin(generation).await?;
+        let res = request().send().await?;
+        check_status(&res)?;
+        if res.url().path() == Session::login_url().path() {
+            return Err(Error::NotLoggedIn(format!(
+                "still logged out after logging in again: {}",
+                url
+            ))
+            .into());
+        }
+        Ok(res)
     }
 }
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::Utc;
+use color_eyre::{eyre::eyre, Result};
+use scraper::ElementRef;
+use serde::Deserialize;
+
+use crate::opds::{CatalogEntry, OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+use super::{
+    download::download_links,
+    session::AuthorizedSession,
+    utils::*,
+    work::{author_feed_uri, Authors},
+    Filters, WorkDetails,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SubscriptionKind {
+    Works,
+    Series,
+    Users,
+}
+
+impl SubscriptionKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Works => "works",
+            SubscriptionKind::Series => "series",
+            SubscriptionKind::Users => "users",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            SubscriptionKind::Works => "Subscribed works",
+            SubscriptionKind::Series => "Subscribed series",
+            SubscriptionKind::Users => "Subscribed users",
+        }
+    }
+
+    /// The catalog entry of [`SubscriptionKind::navigation_feed`].
+    pub(crate) const CATALOG_ENTRY: CatalogEntry = CatalogEntry {
+        id: "subscriptions",
+        title: "Subscriptions",
+        description: "Works, series and creators you are subscribed to",
+        href: "/opds/v1.2/subscriptions",
+        kind: OpdsLinkType::Navigation,
+    };
+
+    /// The top level subscriptions feed, linking to one feed per kind.
+    pub(crate) fn navigation_feed() -> OpdsFeed {
+        OpdsFeed::navigation(
+            Self::CATALOG_ENTRY.id,
+            Self::CATALOG_ENTRY.title,
+            "subscriptions",
+            &Self::REGISTRY,
+        )
+    }
+
+    const REGISTRY: [CatalogEntry; 3] = [
+        CatalogEntry {
+            id: "subscriptions-works",
+            title: "Subscribed works",
+            description: "Works you are subscribed to",
+            href: "/opds/v1.2/subscriptions/works?page=1",
+            kind: OpdsLinkType::Navigation,
+        },
+        CatalogEntry {
+            id: "subscriptions-series",
+            title: "Subscribed series",
+            description: "Series you are subscribed to",
+            href: "/opds/v1.2/subscriptions/series?page=1",
+            kind: OpdsLinkType::Navigation,
+        },
+        CatalogEntry {
+            id: "subscriptions-users",
+            title: "Subscribed users",
+            description: "Creators you are subscribed to",
+            href: "/opds/v1.2/subscriptions/users?page=1",
+            kind: OpdsLinkType::Navigation,
+        },
+    ];
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Subscription {
+    kind: SubscriptionKind,
+    name: String,
+    /// The last path segment of the subscribed uri: a work or series id, or a username.
+    id: String,
+    authors: Authors,
+}
+
+impl Subscription {
+    pub(crate) fn from_element(element: &ElementRef) -> Result<Self> {
+        let link = select_next(element, "a:not([rel=\"author\"])")?;
+        let name = link
+            .text()
+            .next()
+            .ok_or_else(|| eyre!("Issue parsing subscription name"))?
+            .to_string();
+        let uri = link
+            .value()
+            .attr("href")
+            .ok_or_else(|| eyre!("subscription link has no href"))?;
+
+        let (kind, id) = match uri.trim_start_matches('/').split_once('/') {
+            Some(("works", id)) => (SubscriptionKind::Works, id),
+            Some(("series", id)) => (SubscriptionKind::Series, id),
+            Some(("users", id)) => (SubscriptionKind::Users, id),
+            _ => return Err(eyre!("unknown subscription uri: {}", uri)),
+        };
+
+        Ok(Subscription {
+            kind,
+            name,
+            id: id.to_string(),
+            authors: Authors::from_element(element)?,
+        })
+    }
+}
+
+impl Subscription {
+    /// The id of the subscribed work, for subscriptions to works.
+    pub(crate) fn work_id(&self) -> Option<i64> {
+        match self.kind {
+            SubscriptionKind::Works => self.id.parse().ok(),
+            SubscriptionKind::Series | SubscriptionKind::Users => None,
+        }
+    }
+}
+
+impl From<&Subscription> for OpdsEntry {
+    fn from(value: &Subscription) -> Self {
+        let links = match value.kind {
+            SubscriptionKind::Works => download_links(&value.id, &value.name, None),
+            SubscriptionKind::Series => vec![OpdsLink::new(
+                OpdsLinkType::Acquisition,
+                OpdsLinkRel::Subsection,
+                format!("/opds/v1.2/series/{}", value.id),
+            )],
+            SubscriptionKind::Users => vec![OpdsLink::new(
+                OpdsLinkType::Acquisition,
+                OpdsLinkRel::Subsection,
+                author_feed_uri(&value.id, None),
+            )],
+        };
+        OpdsEntry::new(
+            format!("/{}/{}", value.kind.as_str(), value.id),
+            Utc::now().into(),
+            value.name.clone(),
+            None,
+            Some((&value.authors).into()),
+            Some(links),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubscriptionPage {
+    subscriptions: Vec<Subscription>,
+    kind: SubscriptionKind,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl SubscriptionPage {
+    pub(crate) fn from_element(
+        element: &ElementRef,
+        kind: SubscriptionKind,
+        page: usize,
+    ) -> Result<SubscriptionPage> {
+        let mut subscriptions = Vec::new();
+
+        for element in select_all(element, "dl.subscription.index > dt") {
+            subscriptions.push(Subscription::from_element(&element)?);
+        }
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(SubscriptionPage {
+            subscriptions,
+            kind,
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) async fn new(
+        session: &AuthorizedSession,
+        kind: SubscriptionKind,
+        page: usize,
+    ) -> Result<SubscriptionPage> {
+        let html = session.get_subscriptions_page(kind, page).await?;
+        Self::from_element(&html.root_element(), kind, page)
+    }
+
+    pub(crate) fn subscriptions(&self) -> &[Subscription] {
+        &self.subscriptions
+    }
+}
+
+impl
+    From<(
+        Arc<SubscriptionPage>,
+        &Filters<'_>,
+        &HashMap<i64, Arc<WorkDetails>>,
+    )> for OpdsFeed
+{
+    /// Subscriptions only name their works, so works are filtered by the details given for them.
+    /// Series, users and works without details are kept.
+    fn from(
+        (value, filters, details): (
+            Arc<SubscriptionPage>,
+            &Filters<'_>,
+            &HashMap<i64, Arc<WorkDetails>>,
+        ),
+    ) -> Self {
+        let (subscriptions, hidden) = filters.apply_known(&value.subscriptions, |s| {
+            details.get(&s.work_id()?).map(Arc::as_ref)
+        });
+        OpdsFeed::paginated(
+            &format!("subscriptions-{}-page-{}", value.kind.as_str(), value.page),
+            &format!("{} page {}", value.kind.title(), value.page),
+            &format!("subscriptions/{}", value.kind.as_str()),
+            subscriptions,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        )
+        .with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::{SubscriptionKind, SubscriptionPage};
+    use crate::opds::OpdsEntry;
+
+    const PAGE: &str = r#"<html><body>
+<dl class="subscription index group">
+  <dt><a href="/works/123">A Work</a> by <a rel="author" href="/users/someone/pseuds/someone">someone</a></dt>
+  <dd><form action="/users/me/subscriptions/1" method="post"><input type="submit" value="Unsubscribe from A Work"/></form></dd>
+  <dt><a href="/series/7">A Series</a> by <a rel="author" href="/users/other/pseuds/Other%20Name">Other Name (other)</a></dt>
+  <dd><form action="/users/me/subscriptions/2" method="post"><input type="submit" value="Unsubscribe from A Series"/></form></dd>
+  <dt><a href="/users/third">third</a></dt>
+  <dd><form action="/users/me/subscriptions/3" method="post"><input type="submit" value="Unsubscribe from third"/></form></dd>
+</dl>
+<ol class="pagination actions"><li class="next"><a href="/users/me/subscriptions?page=2">Next</a></li></ol>
+</body></html>"#;
+
+    #[test]
+    fn parses_subscriptions_of_every_kind() {
+        let html = Html::parse_document(PAGE);
+        let page = SubscriptionPage::from_element(&html.root_element(), SubscriptionKind::Works, 1)
+            .unwrap();
+        assert!(page.has_next);
+        assert!(!page.has_prev);
+
+        let subscriptions = page.subscriptions();
+        let kinds: Vec<_> = subscriptions.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                SubscriptionKind::Works,
+                SubscriptionKind::Series,
+                SubscriptionKind::Users
+            ]
+        );
+        let names: Vec<_> = subscriptions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["A Work", "A Series", "third"]);
+        assert_eq!(subscriptions[0].authors.to_string(), "someone");
+        assert_eq!(subscriptions[1].authors.to_string(), "Other Name (other)");
+        let work_ids: Vec<_> = subscriptions.iter().map(|s| s.work_id()).collect();
+        assert_eq!(work_ids, [Some(123), None, None]);
+    }
+
+    #[test]
+    fn links_subscriptions_to_their_feeds() {
+        let html = Html::parse_document(PAGE);
+        let page = SubscriptionPage::from_element(&html.root_element(), SubscriptionKind::Works, 1)
+            .unwrap();
+        let entries: Vec<_> = page.subscriptions().iter().map(OpdsEntry::from).collect();
+
+        assert_eq!(entries[0].id, "/works/123");
+        assert!(entries[0].links[0]
+            .href
+            .starts_with("/opds/v1.2/works/123/download."));
+        assert_eq!(entries[1].links[0].href, "/opds/v1.2/series/7");
+        assert_eq!(entries[2].links[0].href, "/opds/v1.2/authors/third/works");
+    }
+}
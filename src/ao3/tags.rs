@@ -0,0 +1,381 @@
+use std::sync::Arc;
+
+use color_eyre::Result;
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use reqwest::Url;
+use scraper::ElementRef;
+
+use crate::error::Error;
+use crate::opds::{CatalogEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+use super::{
+    query::{SearchFacet, SortColumn},
+    required_tags::{Category, Rating, Warning},
+    session::AuthorizedSession,
+    utils::*,
+    Filters, Work, WorkSearchQuery,
+};
+
+/// AO3's media categories, whose tags list every work of their fandoms.
+pub(crate) const MEDIA: [CatalogEntry; 11] = [
+    CatalogEntry {
+        id: "anime",
+        title: "Anime & Manga",
+        description: "Works in anime and manga fandoms",
+        href: "/opds/v1.2/tags/Anime%20%2Aa%2A%20Manga/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "books",
+        title: "Books & Literature",
+        description: "Works in book and literature fandoms",
+        href: "/opds/v1.2/tags/Books%20%2Aa%2A%20Literature/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "comics",
+        title: "Cartoons & Comics & Graphic Novels",
+        description: "Works in cartoon, comic and graphic novel fandoms",
+        href: "/opds/v1.2/tags/Cartoons%20%2Aa%2A%20Comics%20%2Aa%2A%20Graphic%20Novels/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "celebrities",
+        title: "Celebrities & Real People",
+        description: "Works about celebrities and real people",
+        href: "/opds/v1.2/tags/Celebrities%20%2Aa%2A%20Real%20People/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "movies",
+        title: "Movies",
+        description: "Works in movie fandoms",
+        href: "/opds/v1.2/tags/Movies/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "music",
+        title: "Music & Bands",
+        description: "Works in music and band fandoms",
+        href: "/opds/v1.2/tags/Music%20%2Aa%2A%20Bands/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "other",
+        title: "Other Media",
+        description: "Works in fandoms of other media",
+        href: "/opds/v1.2/tags/Other%20Media/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "theater",
+        title: "Theater",
+        description: "Works in theater fandoms",
+        href: "/opds/v1.2/tags/Theater/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "tv",
+        title: "TV Shows",
+        description: "Works in TV show fandoms",
+        href: "/opds/v1.2/tags/TV%20Shows/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "games",
+        title: "Video Games",
+        description: "Works in video game fandoms",
+        href: "/opds/v1.2/tags/Video%20Games/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+    CatalogEntry {
+        id: "uncategorized",
+        title: "Uncategorized Fandoms",
+        description: "Works in fandoms without a media category",
+        href: "/opds/v1.2/tags/Uncategorized%20Fandoms/works",
+        kind: OpdsLinkType::Acquisition,
+    },
+];
+
+/// The catalog entry of [`browse_feed`].
+pub(crate) const BROWSE: CatalogEntry = CatalogEntry {
+    id: "browse",
+    title: "Browse",
+    description: "Browse works by fandom category",
+    href: "/opds/v1.2/browse",
+    kind: OpdsLinkType::Navigation,
+};
+
+/// A navigation feed of the media categories.
+pub(crate) fn browse_feed() -> OpdsFeed {
+    OpdsFeed::navigation(BROWSE.id, BROWSE.title, "browse", &MEDIA)
+}
+
+/// The path of a tag works feed, relative to the feed root.
+pub(crate) fn tag_works_postfix(tag: &str, query: &WorkSearchQuery) -> String {
+    let query = query.to_query_string();
+    if query.is_empty() {
+        format!("tags/{}/works", tag_path_segment(tag))
+    } else {
+        format!("tags/{}/works?{}", tag_path_segment(tag), query)
+    }
+}
+
+/// An option of the filter sidebar, linked as an OPDS facet.
+#[derive(Debug, Clone)]
+pub(crate) struct Facet {
+    group: String,
+    title: String,
+    count: Option<u32>,
+    facet: SearchFacet,
+}
+
+lazy_static! {
+    static ref FACET_RE: Regex = Regex::new(r"^(.+) \((\d+)\)$").unwrap();
+}
+
+impl Facet {
+    /// Parses a `label` of the filter sidebar, wrapping a checkbox like
+    /// `include_work_search[rating_ids][]`.
+    fn from_element(element: &ElementRef) -> Option<Facet> {
+        let input = select_next(element, r#"input[type="checkbox"]"#).ok()?;
+        let name = input.value().attr("name")?;
+        let (include, kind) = match name.strip_prefix("include_work_search[") {
+            Some(kind) => (true, kind),
+            None => (false, name.strip_prefix("exclude_work_search[")?),
+        };
+        let kind = kind.strip_suffix("_ids][]")?;
+
+        let text = element.text().collect::<String>();
+        let text = text.trim();
+        let (title, count) = match FACET_RE.captures(text) {
+            Some(caps) => (caps[1].to_string(), caps[2].parse().ok()),
+            None => (text.to_string(), None),
+        };
+
+        let kind_label = match kind {
+            "rating" => "Ratings",
+            "archive_warning" => "Warnings",
+            "category" => "Categories",
+            "fandom" => "Fandoms",
+            "character" => "Characters",
+            "relationship" => "Relationships",
+            "freeform" => "Additional Tags",
+            _ => return None,
+        };
+        let facet = match (include, kind) {
+            (false, _) => SearchFacet::Exclude(title.clone()),
+            (true, "rating") => SearchFacet::Rating(Rating::from_label(&title)?),
+            (true, "archive_warning") => SearchFacet::Warning(Warning::from_label(&title)?),
+            (true, "category") => SearchFacet::Category(Category::from_label(&title)?),
+            (true, _) => SearchFacet::Include(title.clone()),
+        };
+        let group = if include {
+            format!("Include {}", kind_label)
+        } else {
+            format!("Exclude {}", kind_label)
+        };
+
+        Some(Facet {
+            group,
+            title,
+            count,
+            facet,
+        })
+    }
+
+    fn sort(column: SortColumn) -> Facet {
+        Facet {
+            group: "Sort by".to_string(),
+            title: column.label().to_string(),
+            count: None,
+            facet: SearchFacet::Sort(column),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TagWorksPage {
+    /// The canonical name of the tag, which may differ from the requested one.
+    tag: String,
+    query: WorkSearchQuery,
+    works: Vec<Work>,
+    facets: Vec<Facet>,
+    page: usize,
+    has_next: bool,
+    has_prev: bool,
+}
+
+impl TagWorksPage {
+    pub(crate) fn from_element(
+        element: &ElementRef,
+        tag: String,
+        query: WorkSearchQuery,
+        page: usize,
+    ) -> Result<TagWorksPage> {
+        let mut works = Vec::new();
+        for element in select_all(element, "ol.work.index > li.work.blurb") {
+            works.push(Work::from_element(&element)?);
+        }
+
+        let mut facets: Vec<Facet> = SortColumn::ALL
+            .iter()
+            .filter(|column| **column != SortColumn::Relevance)
+            .map(|column| Facet::sort(*column))
+            .collect();
+        facets.extend(
+            select_all(element, "form#work-filters label")
+                .iter()
+                .filter_map(Facet::from_element),
+        );
+
+        let has_prev = select_next(element, "ol.pagination > li.previous > a")
+            .ok()
+            .is_some();
+        let has_next = select_next(element, "ol.pagination > li.next > a")
+            .ok()
+            .is_some();
+
+        Ok(TagWorksPage {
+            tag,
+            query,
+            works,
+            facets,
+            page,
+            has_next,
+            has_prev,
+        })
+    }
+
+    pub(crate) async fn new(
+        session: &AuthorizedSession,
+        tag: &str,
+        query: WorkSearchQuery,
+        page: usize,
+    ) -> Result<TagWorksPage> {
+        let (url, html) = session.get_tag_works_page(tag, &query, page).await?;
+        // synonyms redirect to their canonical tag, unwrangled tags to the tag page
+        let canonical = canonical_tag(&url).ok_or_else(|| {
+            Error::BadInput(format!("\"{}\" is not a canonical tag with works", tag))
+        })?;
+        Self::from_element(&html.root_element(), canonical, query, page)
+    }
+
+    pub(crate) fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub(crate) fn works(&self) -> &[Work] {
+        &self.works
+    }
+}
+
+/// The tag name of a `/tags/{tag}/works` url.
+fn canonical_tag(url: &Url) -> Option<String> {
+    match url.path_segments()?.collect::<Vec<_>>()[..] {
+        ["tags", tag, "works"] => Some(unescape_tag(&percent_decode_str(tag).decode_utf8_lossy())),
+        _ => None,
+    }
+}
+
+impl From<(Arc<TagWorksPage>, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (Arc<TagWorksPage>, &Filters<'_>)) -> Self {
+        let (works, hidden) = filters.apply(&value.works, |w| w);
+        let mut title = value.tag.clone();
+        let description = value.query.describe();
+        if !description.is_empty() {
+            title = format!("{} ({})", title, description);
+        }
+        let mut feed = OpdsFeed::paginated(
+            &format!(
+                "tags-{}-{}-page-{}",
+                value.tag,
+                value.query.to_query_string(),
+                value.page
+            ),
+            &title,
+            &tag_works_postfix(&value.tag, &value.query),
+            works,
+            value.page,
+            value.has_next,
+            value.has_prev,
+        );
+        feed.links
+            .get_or_insert_with(Vec::new)
+            .extend(value.facets.iter().map(|facet| {
+                OpdsLink::new(
+                    OpdsLinkType::Acquisition,
+                    OpdsLinkRel::Facet,
+                    format!(
+                        "/opds/v1.2/{}",
+                        tag_works_postfix(&value.tag, &value.query.toggle(&facet.facet))
+                    ),
+                )
+                .with_title(&facet.title)
+                .with_facet(
+                    &facet.group,
+                    value.query.is_active(&facet.facet),
+                    facet.count,
+                )
+            }));
+        feed.with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use scraper::Html;
+
+    use super::{canonical_tag, tag_works_postfix, Facet, MEDIA};
+    use crate::ao3::{
+        query::SearchFacet, required_tags::Rating, utils::unescape_tag, WorkSearchQuery,
+    };
+
+    #[test]
+    fn media_links_match_tag_feeds() {
+        for media in MEDIA {
+            let href = format!(
+                "/opds/v1.2/{}",
+                tag_works_postfix(media.title, &WorkSearchQuery::default())
+            );
+            assert_eq!(media.href, href);
+        }
+    }
+
+    #[test]
+    fn reads_canonical_tag_from_redirect() {
+        let url = Url::parse(
+            "https://archiveofourown.org/tags/Harry%20Potter*s*Draco%20Malfoy/works?page=1",
+        )
+        .unwrap();
+        assert_eq!(
+            canonical_tag(&url).as_deref(),
+            Some("Harry Potter/Draco Malfoy")
+        );
+        let url = Url::parse("https://archiveofourown.org/tags/some%20tag").unwrap();
+        assert_eq!(canonical_tag(&url), None);
+        assert_eq!(unescape_tag("A *a* B *d* C"), "A & B . C");
+    }
+
+    #[test]
+    fn parses_sidebar_facets() {
+        let html = Html::parse_fragment(
+            r#"<form id="work-filters">
+<label><input type="checkbox" name="include_work_search[rating_ids][]" value="13"><span>Explicit (12)</span></label>
+<label><input type="checkbox" name="exclude_work_search[freeform_ids][]" value="1"><span>Angst (3)</span></label>
+</form>"#,
+        );
+        let facets: Vec<_> = crate::ao3::utils::select_all(&html.root_element(), "label")
+            .iter()
+            .filter_map(Facet::from_element)
+            .collect();
+        assert_eq!(facets[0].group, "Include Ratings");
+        assert_eq!(facets[0].facet, SearchFacet::Rating(Rating::Explicit));
+        assert_eq!(facets[0].count, Some(12));
+        assert_eq!(facets[1].group, "Exclude Additional Tags");
+        assert_eq!(facets[1].facet, SearchFacet::Exclude("Angst".to_string()));
+    }
+}
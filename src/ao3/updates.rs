@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
+};
+
+use color_eyre::Result;
+use tokio::fs;
+
+use crate::opds::{OpdsEntry, OpdsFeed};
+
+use super::{history::HistoryWork, Filters, HistoryPage};
+
+/// Remembers how many chapters works had while they were up to date in the history.
+///
+/// AO3 does not show how far a work was when it was last visited, so this is only known for works
+/// this server saw before they were updated. The log is kept in a JSON file, so it survives
+/// restarts.
+#[derive(Debug, Default)]
+pub(crate) struct ChapterLog {
+    path: Option<PathBuf>,
+    chapters: Mutex<HashMap<i64, i32>>,
+    /// Whether there are chapters which weren't saved yet.
+    changed: AtomicBool,
+}
+
+impl ChapterLog {
+    /// Loads the log from `path`, which is empty if the file doesn't exist.
+    pub(crate) async fn open(path: PathBuf) -> Result<Self> {
+        let chapters = match fs::read_to_string(&path).await {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            chapters: Mutex::new(chapters),
+            changed: AtomicBool::new(false),
+        })
+    }
+
+    /// Writes the log to its file, if anything was recorded since it was last saved.
+    pub(crate) async fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.changed.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let json =
+            serde_json::to_string(&*self.chapters.lock().expect("chapter log lock poisoned"))?;
+        fs::write(path, json).await?;
+        Ok(())
+    }
+
+    fn record(&self, work: &HistoryWork) {
+        if !work.has_update() {
+            let chapters = work.work().chapters().written();
+            let previous = self
+                .chapters
+                .lock()
+                .expect("chapter log lock poisoned")
+                .insert(work.work().id(), chapters);
+            if previous != Some(chapters) {
+                self.changed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn get(&self, id: i64) -> Option<i32> {
+        self.chapters
+            .lock()
+            .expect("chapter log lock poisoned")
+            .get(&id)
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct UpdatedWork {
+    work: HistoryWork,
+    /// The chapters posted when the work was last seen up to date, if known.
+    read_chapters: Option<i32>,
+}
+
+impl From<&UpdatedWork> for OpdsEntry {
+    fn from(value: &UpdatedWork) -> Self {
+        let mut entry: OpdsEntry = (&value.work).into();
+        let chapters = value.work.work().chapters();
+        let line = match value.read_chapters {
+            Some(read) if chapters.written() > read => format!(
+                "{} new chapters since your last visit ({} → {})",
+                chapters.written() - read,
+                read,
+                chapters
+            ),
+            _ => format!(
+                "Updated since your last visit, now at {} chapters",
+                chapters
+            ),
+        };
+        entry.push_content(&line);
+        entry
+    }
+}
+
+/// The works in the whole history which were updated since they were last visited.
+#[derive(Debug, Clone)]
+pub(crate) struct Updates {
+    works: Vec<UpdatedWork>,
+}
+
+impl Updates {
+    /// Collects the updated works of all history pages, most recently updated first.
+    pub(crate) fn new(pages: &[Arc<HistoryPage>], log: &ChapterLog) -> Updates {
+        let mut works = Vec::new();
+        for work in pages.iter().flat_map(|page| page.works()) {
+            if work.has_update() {
+                works.push(UpdatedWork {
+                    work: work.clone(),
+                    read_chapters: log.get(work.work().id()),
+                });
+            } else {
+                log.record(work);
+            }
+        }
+        works.sort_by_key(|updated| std::cmp::Reverse(updated.work.work().last_updated()));
+        Updates { works }
+    }
+}
+
+impl From<(&Updates, &Filters<'_>)> for OpdsFeed {
+    fn from((value, filters): (&Updates, &Filters<'_>)) -> Self {
+        let (works, hidden) = filters.apply(&value.works, |u| u.work.work());
+        OpdsFeed::paginated(
+            "updates",
+            "Updated since I last read",
+            "updates",
+            works,
+            1,
+            false,
+            false,
+        )
+        .with_hidden(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use scraper::Html;
+
+    use super::{ChapterLog, Updates};
+    use crate::{
+        ao3::{
+            history::{HistoryPage, Readings},
+            work::tests::blurb,
+        },
+        opds::OpdsEntry,
+    };
+
+    fn page(works: &[(i64, &str, &str, &str)]) -> Arc<HistoryPage> {
+        let items: String = works
+            .iter()
+            .map(|(id, updated, chapters, changed)| {
+                let visited = format!(
+                    "<div class=\"user module group\"><h4 class=\"viewed heading\">\
+                     <span>Last visited:</span> 01 Oct 2026\n\n  ({})\n\n  Visited 2 times\n</h4></div>",
+                    changed
+                );
+                blurb("reading work", *id, updated, chapters, &visited)
+            })
+            .collect();
+        let html = Html::parse_fragment(&format!(r#"<ol class="reading index">{}</ol>"#, items));
+        Arc::new(HistoryPage::from_element(&html.root_element(), Readings::History, 1).unwrap())
+    }
+
+    #[test]
+    fn lists_updated_works_newest_first() {
+        let pages = [
+            page(&[
+                (1, "02 Oct 2026", "2/3", "Update available."),
+                (2, "04 Oct 2026", "1/1", "Latest version."),
+            ]),
+            page(&[(3, "05 Oct 2026", "4/?", "Update available.")]),
+        ];
+        let updates = Updates::new(&pages, &ChapterLog::default());
+        let ids: Vec<_> = updates.works.iter().map(|u| u.work.work().id()).collect();
+        assert_eq!(ids, [3, 1]);
+    }
+
+    #[test]
+    fn counts_chapters_since_the_work_was_up_to_date() {
+        let log = ChapterLog::default();
+        Updates::new(
+            &[page(&[(1, "02 Oct 2026", "2/3", "Latest version.")])],
+            &log,
+        );
+        assert_eq!(log.get(1), Some(2));
+
+        let updates = Updates::new(
+            &[page(&[
+                (1, "09 Oct 2026", "3/3", "Update available."),
+                (2, "09 Oct 2026", "5/?", "Update available."),
+            ])],
+            &log,
+        );
+        let content: Vec<_> = updates
+            .works
+            .iter()
+            .map(|u| OpdsEntry::from(u).content.unwrap())
+            .collect();
+        assert!(content[0].ends_with("1 new chapters since your last visit (2 → 3/3)"));
+        assert!(content[1].ends_with("Updated since your last visit, now at 5/? chapters"));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_log_across_restarts() {
+        let path =
+            std::env::temp_dir().join(format!("ao3-opds-chapters-{}.json", std::process::id()));
+
+        let log = ChapterLog::open(path.clone()).await.unwrap();
+        assert_eq!(log.get(1), None);
+        Updates::new(
+            &[page(&[(1, "02 Oct 2026", "2/3", "Latest version.")])],
+            &log,
+        );
+        log.save().await.unwrap();
+
+        let log = ChapterLog::open(path.clone()).await.unwrap();
+        assert_eq!(log.get(1), Some(2));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,17 +1,19 @@
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
 use color_eyre::{eyre::eyre, Result};
 use lazy_static::lazy_static;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use scraper::{ElementRef, Selector};
 
+use crate::error::Error;
+
 pub(crate) fn select_next<'a>(
     html: &'a ElementRef<'a>,
     selector: &'a str,
 ) -> Result<ElementRef<'a>> {
     let sel = Selector::parse(selector).or(Err(eyre!("Could not parse selector")))?;
-    let result = html
-        .select(&sel)
-        .next()
-        .ok_or_else(|| eyre!("No element found with {}", selector))?;
+    let result = html.select(&sel).next().ok_or_else(|| Error::Parse {
+        selector: selector.to_string(),
+    })?;
     Ok(result)
 }
 
@@ -53,6 +55,52 @@ lazy_static! {
     pub(crate) static ref DT_DEFAULT: DateTime<FixedOffset> = DateTime::<Utc>::MIN_UTC.into();
 }
 
+fn date_parse(s: &str, fmt: &str) -> DateTime<FixedOffset> {
+    NaiveDate::parse_from_str(s.trim(), fmt)
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map_or(*DT_DEFAULT, |date| Utc.from_utc_datetime(&date).into())
+}
+
+/// Parses dates as shown on blurbs, like `03 Oct 2026`.
 pub(crate) fn ao3_dt_parse(s: &str) -> DateTime<FixedOffset> {
-    DateTime::parse_from_str(s, "%d %b %Y").unwrap_or(*DT_DEFAULT)
+    date_parse(s, "%d %b %Y")
+}
+
+/// Parses dates as shown on work pages, like `2026-10-03`.
+pub(crate) fn ao3_iso_dt_parse(s: &str) -> DateTime<FixedOffset> {
+    date_parse(s, "%Y-%m-%d")
+}
+
+/// Formats dates for entry content, like `3 Oct 2026`.
+pub(crate) fn ao3_dt_format(dt: &DateTime<FixedOffset>) -> String {
+    dt.format("%-d %b %Y").to_string()
+}
+
+/// Escapes a tag name the way AO3 does in tag urls, before percent encoding.
+pub(crate) fn escape_tag(tag: &str) -> String {
+    tag.replace('/', "*s*")
+        .replace('&', "*a*")
+        .replace('.', "*d*")
+        .replace('?', "*q*")
+        .replace('#', "*h*")
+}
+
+/// Reverses [`escape_tag`].
+pub(crate) fn unescape_tag(tag: &str) -> String {
+    tag.replace("*s*", "/")
+        .replace("*a*", "&")
+        .replace("*d*", ".")
+        .replace("*q*", "?")
+        .replace("*h*", "#")
+}
+
+/// Escapes and percent encodes a tag name for use as a single url path segment.
+pub(crate) fn tag_path_segment(tag: &str) -> String {
+    utf8_percent_encode(&escape_tag(tag), NON_ALPHANUMERIC).to_string()
+}
+
+/// Percent-encodes a value for use in a query string.
+pub(crate) fn query_value(value: &str) -> String {
+    utf8_percent_encode(value, NON_ALPHANUMERIC).to_string()
 }
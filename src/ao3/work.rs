@@ -1,30 +1,104 @@
 use chrono::{DateTime, FixedOffset};
 use color_eyre::{eyre::eyre, Result};
+use percent_encoding::percent_decode_str;
 use scraper::ElementRef;
 
-use super::utils::*;
+use super::{
+    cover::{cover_links, Cover},
+    download::{download_links, revision},
+    filter::{FilterSubject, Filterable},
+    required_tags::RequiredTags,
+    utils::*,
+};
+use crate::opds::OpdsCategory;
 use crate::opds::OpdsLinkRel;
 use crate::opds::OpdsLinkType;
 use crate::opds::StumpAuthor;
-use crate::opds::{OpdsEntry, OpdsLink};
+use crate::opds::{OpdsEntry, OpdsLink, OpdsStats};
 
 #[derive(Debug, Clone)]
-pub(crate) struct Authors(Vec<String>);
+pub(crate) struct Author {
+    name: String,
+    user: String,
+    pseud: Option<String>,
+}
+
+impl Author {
+    /// Parses an author link like `/users/X` or `/users/X/pseuds/Y`.
+    pub(crate) fn from_element(element: &ElementRef) -> Result<Author> {
+        let name = element
+            .text()
+            .next()
+            .ok_or_else(|| eyre!("Issue parsing author"))?
+            .to_string();
+        let href = element
+            .value()
+            .attr("href")
+            .ok_or_else(|| eyre!("author {} has no link", name))?;
+        let mut segments = href
+            .strip_prefix("/users/")
+            .ok_or_else(|| eyre!("could not parse author link: {}", href))?
+            .split('/');
+        let user = segments.next().unwrap_or_default().to_string();
+        let pseud = match (segments.next(), segments.next()) {
+            (Some("pseuds"), Some(pseud)) if pseud != user => {
+                Some(percent_decode_str(pseud).decode_utf8_lossy().to_string())
+            }
+            _ => None,
+        };
+
+        Ok(Author { name, user, pseud })
+    }
+
+    /// Links to the works feed of this author, limited to the pseud if any.
+    pub(crate) fn feed_uri(&self) -> String {
+        author_feed_uri(&self.user, self.pseud.as_deref())
+    }
+}
+
+/// The works feed of an AO3 user, optionally limited to one of their pseuds.
+pub(crate) fn author_feed_uri(user: &str, pseud: Option<&str>) -> String {
+    match pseud {
+        Some(pseud) => format!(
+            "/opds/v1.2/authors/{}/works?pseud={}",
+            user,
+            query_value(pseud)
+        ),
+        None => format!("/opds/v1.2/authors/{}/works", user),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Authors(Vec<Author>);
 
 impl Authors {
     pub(crate) fn from_element(element: &ElementRef) -> Result<Authors> {
         let mut authors = Vec::new();
         for a in select_all(element, r#"a[rel="author"]"#) {
-            authors.push(
-                a.text()
-                    .next()
-                    .ok_or_else(|| eyre!("Issue parsing author"))?
-                    .to_string(),
-            );
+            authors.push(Author::from_element(&a)?);
         }
 
         Ok(Authors(authors))
     }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.0.iter().map(|author| author.name.clone()).collect()
+    }
+
+    /// Sorts works by their first author, case insensitively.
+    pub(crate) fn sort_key(&self) -> String {
+        self.0
+            .first()
+            .map(|author| author.name.to_lowercase())
+            .unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Authors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.0.iter().map(|a| a.name.as_str()).collect();
+        f.write_str(&names.join(", "))
+    }
 }
 
 impl From<&Authors> for Vec<StumpAuthor> {
@@ -32,7 +106,7 @@ impl From<&Authors> for Vec<StumpAuthor> {
         value
             .0
             .iter()
-            .map(|name| StumpAuthor::new(name.to_string(), None))
+            .map(|author| StumpAuthor::new(author.name.clone(), Some(author.feed_uri())))
             .collect()
     }
 }
@@ -69,7 +143,10 @@ impl Tags {
             vec
         };
         Ok(Tags {
-            warnings: vec![select_next_str(element, "li.warnings > strong > a")?],
+            warnings: select_all(element, "li.warnings a")
+                .iter()
+                .filter_map(|a| a.text().next().map(str::to_string))
+                .collect(),
             relationships,
             characters,
             freeform,
@@ -85,29 +162,135 @@ pub(crate) struct SeriesRef {
 }
 
 impl SeriesRef {
+    /// Parses one `li` of a blurb's series list.
     pub(crate) fn from_element(element: &ElementRef) -> Result<Self> {
-        let part = select_int(element, "li > strong")?;
+        let part = select_int(element, "strong")?;
         let name = select_next_str(element, "a")?;
         let uri = select_next_attr(element, "a", "href")?;
 
         Ok(SeriesRef { name, uri, part })
     }
+
+    /// Parses the `Part 2 of <a>Series</a>` position shown on work pages.
+    pub(crate) fn from_position(element: &ElementRef) -> Result<Self> {
+        let text = element.text().collect::<String>();
+        let part = text
+            .trim()
+            .strip_prefix("Part ")
+            .and_then(|s| s.split_once(' '))
+            .ok_or_else(|| eyre!("could not parse series position: {}", text))?
+            .0
+            .parse()?;
+        let name = select_next_str(element, "a")?;
+        let uri = select_next_attr(element, "a", "href")?;
+
+        Ok(SeriesRef { name, uri, part })
+    }
+
+    /// The AO3 series id, from the series uri.
+    pub(crate) fn id(&self) -> Option<i64> {
+        self.uri.strip_prefix("/series/")?.parse().ok()
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn part(&self) -> i32 {
+        self.part
+    }
+
+    /// Links to the feed of the series.
+    pub(crate) fn link(&self) -> Option<OpdsLink> {
+        Some(
+            OpdsLink::new(
+                OpdsLinkType::Acquisition,
+                OpdsLinkRel::Collection,
+                format!("/opds/v1.2/series/{}", self.id()?),
+            )
+            .with_title(&self.name),
+        )
+    }
+
+    pub(crate) fn category(&self) -> OpdsCategory {
+        OpdsCategory::new(
+            tag_scheme("series"),
+            self.name.clone(),
+            Some(format!("Part {} of {}", self.part, self.name)),
+        )
+    }
+}
+
+/// Links to the works feed of a tag.
+pub(crate) fn tag_feed_link(tag: &str) -> OpdsLink {
+    OpdsLink::new(
+        OpdsLinkType::Acquisition,
+        OpdsLinkRel::Related,
+        format!("/opds/v1.2/tags/{}/works", tag_path_segment(tag)),
+    )
+    .with_title(tag)
+}
+
+/// The category scheme for a kind of AO3 tag, like `fandom` or `freeform`.
+pub(crate) fn tag_scheme(kind: &str) -> String {
+    format!("https://archiveofourown.org/tags#{}", kind)
+}
+
+pub(crate) fn tag_categories<'a>(
+    kind: &'a str,
+    tags: &'a [String],
+) -> impl Iterator<Item = OpdsCategory> + 'a {
+    tags.iter()
+        .map(move |tag| OpdsCategory::new(tag_scheme(kind), tag.clone(), Some(tag.clone())))
 }
 
 #[derive(Debug, Clone)]
-enum Chapters {
+pub(crate) enum Chapters {
     Known(i32, i32),
     Unknown(i32),
 }
 
+impl Chapters {
+    /// Parses AO3's `written/total` chapter count, where the total may be `?`.
+    pub(crate) fn parse(chapters: &str) -> Result<Self> {
+        let (a, b) = chapters
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| eyre!("could not split chapter: {}", chapters))?;
+        let a = a.parse()?;
+        Ok(match b.parse() {
+            Ok(b) => Chapters::Known(a, b),
+            Err(_) => Chapters::Unknown(a),
+        })
+    }
+
+    /// The number of chapters posted so far.
+    pub(crate) fn written(&self) -> i32 {
+        match self {
+            Chapters::Known(written, _) | Chapters::Unknown(written) => *written,
+        }
+    }
+}
+
+impl std::fmt::Display for Chapters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chapters::Known(a, b) => write!(f, "{}/{}", a, b),
+            Chapters::Unknown(a) => write!(f, "{}/?", a),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Work {
-    authors: Authors, // TODO: Fandom!
+    authors: Authors,
+    fandoms: Vec<String>,
+    required_tags: RequiredTags,
     title: String,
     id: i64,
     tags: Tags,
     summary: String,
-    series: Option<SeriesRef>,
+    series: Vec<SeriesRef>,
     last_updated: DateTime<FixedOffset>,
     language: String,
     words: i32,
@@ -125,30 +308,26 @@ impl Work {
         let uri = select_next_attr(&heading, "a", "href")?;
         let id = uri
             .split('/')
-            .last()
+            .next_back()
             .ok_or_else(|| eyre!("could not split uri: {}", uri))?
             .parse::<i64>()?;
         let last_updated = ao3_dt_parse(&select_next_str(element, "div > p.datetime")?);
 
-        let chapters = {
-            let chapters = select_string(element, "dl.stats > dd.chapters")?;
-            let (a, b) = chapters
-                .split_once('/')
-                .ok_or_else(|| eyre!("could not split chapter: {}", chapters))?;
-            let a = a.parse()?;
-            match b.parse() {
-                Ok(b) => Chapters::Known(a, b),
-                Err(_) => Chapters::Unknown(a),
-            }
-        };
+        let chapters = Chapters::parse(&select_string(element, "dl.stats > dd.chapters")?)?;
 
-        let series = select_next(element, "ul.series")
-            .ok()
-            .and_then(|e| SeriesRef::from_element(&e).ok());
+        let series = select_all(element, "ul.series > li")
+            .iter()
+            .filter_map(|e| SeriesRef::from_element(e).ok())
+            .collect();
 
         let tags_element = select_next(element, r#"ul.tags"#)?;
         Ok(Work {
             authors: Authors::from_element(&heading)?,
+            fandoms: select_all(element, "h5.fandoms > a.tag")
+                .iter()
+                .filter_map(|a| a.text().next().map(str::to_string))
+                .collect(),
+            required_tags: RequiredTags::from_element(&select_next(element, "ul.required-tags")?)?,
             title,
             id,
             tags: Tags::from_element(&tags_element)?,
@@ -158,35 +337,252 @@ impl Work {
             language: select_next_str(element, "dl.stats > dd.language")?,
             words: select_int(element, "dl.stats > dd.words")?,
             chapters,
-            comments: select_int(element, "dl.stats > dd.comments > a")?,
-            kudos: select_int(element, "dl.stats > dd.kudos > a")?,
+            comments: select_int(element, "dl.stats > dd.comments > a").unwrap_or(0),
+            kudos: select_int(element, "dl.stats > dd.kudos > a").unwrap_or(0),
             bookmarks: select_int(element, "dl.stats > dd.bookmarks > a").unwrap_or(0),
             hits: select_int(element, "dl.stats > dd.hits")?,
         })
     }
 }
 
+impl Work {
+    pub(crate) fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub(crate) fn last_updated(&self) -> DateTime<FixedOffset> {
+        self.last_updated
+    }
+
+    pub(crate) fn chapters(&self) -> &Chapters {
+        &self.chapters
+    }
+
+    pub(crate) fn authors(&self) -> &Authors {
+        &self.authors
+    }
+
+    /// What goes on the generated cover of the work.
+    pub(crate) fn cover(&self) -> Cover {
+        Cover {
+            title: self.title.clone(),
+            authors: self.authors.to_string(),
+            fandoms: self.fandoms.clone(),
+            rating: self.required_tags.rating,
+            words: self.words,
+        }
+    }
+
+    /// The part of this work in the series with the given id.
+    pub(crate) fn series_part(&self, series_id: i64) -> Option<i32> {
+        self.series
+            .iter()
+            .find(|s| s.id() == Some(series_id))
+            .map(SeriesRef::part)
+    }
+}
+
+impl Filterable for Work {
+    fn filter_subject(&self) -> FilterSubject<'_> {
+        FilterSubject {
+            rating: self.required_tags.rating,
+            warnings: &self.required_tags.warnings,
+            fandoms: &self.fandoms,
+            relationships: &self.tags.relationships,
+            freeforms: &self.tags.freeform,
+            language: &self.language,
+            words: self.words,
+            completion: self.required_tags.completion,
+        }
+    }
+}
+
 impl From<&Work> for OpdsEntry {
     fn from(value: &Work) -> Self {
-        let content: String = format!(
-            r"[{}], [{}], [{}], [{}]\n{}",
-            value.tags.warnings.first().unwrap_or(&"".to_string()),
-            value.tags.relationships.first().unwrap_or(&"".to_string()),
-            value.tags.characters.first().unwrap_or(&"".to_string()),
-            value.tags.freeform.first().unwrap_or(&"".to_string()),
-            value.summary,
-        );
-        Self::new(
+        let mut lines = vec![value.required_tags.to_string()];
+        for (label, tags) in [
+            ("Warnings", &value.tags.warnings),
+            ("Relationships", &value.tags.relationships),
+            ("Characters", &value.tags.characters),
+            ("Tags", &value.tags.freeform),
+        ] {
+            if !tags.is_empty() {
+                lines.push(format!("{}: {}", label, tags.join(", ")));
+            }
+        }
+        lines.push(value.summary.clone());
+        let content = lines.join("\n");
+        let mut entry = Self::new(
             format!("/works/{}", value.id),
             value.last_updated,
             value.title.clone(),
             Some(content),
             Some((&value.authors).into()),
             Some(vec![OpdsLink::new(
-                OpdsLinkType::Epub,
-                OpdsLinkRel::Acquisition,
-                format!("https://archiveofourown.org/downloads/{}/a.epub", value.id),
+                OpdsLinkType::Entry,
+                OpdsLinkRel::Alternate,
+                format!("/opds/v1.2/works/{}", value.id),
             )]),
+        );
+        entry.links.extend(download_links(
+            value.id,
+            &value.title,
+            Some(&revision(value.last_updated, &value.chapters)),
+        ));
+        entry.links.extend(cover_links(value.id));
+        entry.stats = Some(OpdsStats {
+            chapters: value.chapters.to_string(),
+            words: value.words,
+            comments: value.comments,
+            kudos: value.kudos,
+            bookmarks: value.bookmarks,
+            hits: value.hits,
+        });
+        entry.categories.extend(value.required_tags.categories());
+        entry
+            .categories
+            .extend(value.series.iter().map(SeriesRef::category));
+        entry
+            .links
+            .extend(value.series.iter().filter_map(SeriesRef::link));
+        entry
+            .categories
+            .extend(tag_categories("fandom", &value.fandoms));
+        entry
+            .links
+            .extend(value.fandoms.iter().map(|fandom| tag_feed_link(fandom)));
+        entry
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use scraper::Html;
+
+    use super::{Authors, Work};
+    use crate::opds::{OpdsEntry, StumpAuthor};
+
+    /// A work blurb as AO3 lists it, in an `li` of the given class, with the `user` markup of
+    /// lists like the history at its end.
+    pub(crate) fn blurb(class: &str, id: i64, updated: &str, chapters: &str, user: &str) -> String {
+        format!(
+            r#"<li id="work_{id}" class="{class} blurb group" role="article">
+<div class="header module">
+<h4 class="heading"><a href="/works/{id}">Work {id}</a> by <a rel="author" href="/users/someone/pseuds/someone">someone</a></h4>
+<h5 class="fandoms heading"><span class="landmark">Fandoms:</span> <a class="tag" href="/tags/Some%20Fandom/works">Some Fandom</a></h5>
+<ul class="required-tags">
+<li><a><span class="rating-teen rating" title="Teen And Up Audiences"><span class="text">Teen And Up Audiences</span></span></a></li>
+<li><a><span class="warning-no warnings" title="No Archive Warnings Apply"><span class="text">No Archive Warnings Apply</span></span></a></li>
+<li><a><span class="category-slash category" title="M/M"><span class="text">M/M</span></span></a></li>
+<li><a><span class="complete-no iswip" title="Work in Progress"><span class="text">Work in Progress</span></span></a></li>
+</ul>
+<p class="datetime">{updated}</p>
+</div>
+<ul class="tags commas">
+<li class="warnings"><strong><a class="tag" href="/tags/No%20Archive%20Warnings%20Apply/works">No Archive Warnings Apply</a></strong></li>
+<li class="relationships"><a class="tag" href="/tags/A*s*B/works">A/B</a></li>
+<li class="characters"><a class="tag" href="/tags/A/works">A</a></li>
+<li class="freeforms"><a class="tag" href="/tags/Fluff/works">Fluff</a></li>
+</ul>
+<blockquote class="userstuff summary"><p>A summary.</p></blockquote>
+<dl class="stats">
+<dt class="language">Language:</dt><dd class="language">English</dd>
+<dt class="words">Words:</dt><dd class="words">1,234</dd>
+<dt class="chapters">Chapters:</dt><dd class="chapters">{chapters}</dd>
+<dt class="kudos">Kudos:</dt><dd class="kudos"><a href="/works/{id}#kudos">7</a></dd>
+<dt class="hits">Hits:</dt><dd class="hits">56</dd>
+</dl>
+{user}
+</li>"#,
+            class = class,
+            id = id,
+            updated = updated,
+            chapters = chapters,
+            user = user,
         )
     }
+
+    #[test]
+    fn parses_fandoms_into_categories_and_links() {
+        let html = Html::parse_fragment(&blurb("work", 1, "03 Oct 2026", "1/1", ""));
+        let work = Work::from_element(&html.root_element()).unwrap();
+        assert_eq!(work.fandoms, ["Some Fandom"]);
+
+        let entry = OpdsEntry::from(&work);
+        let fandom = entry
+            .categories
+            .iter()
+            .find(|c| c.scheme == "https://archiveofourown.org/tags#fandom")
+            .unwrap();
+        assert_eq!(fandom.term, "Some Fandom");
+        let link = entry
+            .links
+            .iter()
+            .find(|l| l.title.as_deref() == Some("Some Fandom"))
+            .unwrap();
+        assert_eq!(link.href, "/opds/v1.2/tags/Some%20Fandom/works");
+        assert_eq!(link.rel, "related");
+    }
+
+    #[test]
+    fn exposes_blurb_stats() {
+        let html = Html::parse_fragment(&blurb("work", 1, "03 Oct 2026", "2/?", ""));
+        let work = Work::from_element(&html.root_element()).unwrap();
+        let xml = quick_xml::se::to_string(&OpdsEntry::from(&work)).unwrap();
+        assert!(xml.contains(
+            r#"<ao3:stats chapters="2/?" words="1234" comments="0" kudos="7" bookmarks="0" hits="56"/>"#
+        ));
+    }
+
+    #[test]
+    fn puts_tags_and_summary_on_separate_lines() {
+        let html = Html::parse_fragment(
+            &blurb("work", 1, "03 Oct 2026", "1/1", "").replace(
+                r#"<li class="characters">"#,
+                r#"<li class="relationships"><a class="tag" href="/tags/A%20*a*%20C/works">A &amp; C</a></li>
+<li class="characters">"#,
+            ),
+        );
+        let work = Work::from_element(&html.root_element()).unwrap();
+        let content = OpdsEntry::from(&work).content.unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Teen And Up Audiences | No Archive Warnings Apply | M/M | Work in Progress",
+                "Warnings: No Archive Warnings Apply",
+                "Relationships: A/B, A & C",
+                "Characters: A",
+                "Tags: Fluff",
+                "A summary.",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_author_links() {
+        let html = Html::parse_fragment(
+            r#"<h4 class="heading">
+<a rel="author" href="/users/someone/pseuds/someone">someone</a>,
+<a rel="author" href="/users/other/pseuds/Other%20Name">Other Name (other)</a>
+</h4>"#,
+        );
+        let authors = Authors::from_element(&html.root_element()).unwrap();
+        assert_eq!(authors.to_string(), "someone, Other Name (other)");
+        let uris: Vec<_> = Vec::<StumpAuthor>::from(&authors)
+            .into_iter()
+            .map(|a| a.uri.unwrap())
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                "/opds/v1.2/authors/someone/works",
+                "/opds/v1.2/authors/other/works?pseud=Other%20Name",
+            ]
+        );
+    }
 }
@@ -0,0 +1,331 @@
+use chrono::{DateTime, FixedOffset};
+use color_eyre::Result;
+use scraper::ElementRef;
+
+use crate::opds::{OpdsEntry, OpdsLink, OpdsLinkRel, OpdsLinkType, OpdsStats};
+
+use super::{
+    cover::{cover_links, Cover},
+    download::{download_links, revision},
+    epub::EpubMetadata,
+    filter::{FilterSubject, Filterable},
+    required_tags::{Category, Completion, Rating, Warning},
+    session::AuthorizedSession,
+    utils::*,
+    work::{tag_categories, tag_feed_link, Authors, Chapters, SeriesRef},
+};
+
+/// A work as shown on its own page, with all of its metadata.
+#[derive(Debug, Clone)]
+pub(crate) struct WorkDetails {
+    id: i64,
+    title: String,
+    authors: Authors,
+    rating: Option<Rating>,
+    warnings: Vec<Warning>,
+    categories: Vec<Category>,
+    fandoms: Vec<String>,
+    relationships: Vec<String>,
+    characters: Vec<String>,
+    freeform: Vec<String>,
+    /// The language code, like `en`.
+    language: String,
+    /// The language name, like `English`, which filters use.
+    language_name: String,
+    series: Vec<SeriesRef>,
+    published: DateTime<FixedOffset>,
+    /// The last update, which is the completion date for complete works.
+    updated: Option<DateTime<FixedOffset>>,
+    /// When the work was completed, if it is.
+    completed: Option<DateTime<FixedOffset>>,
+    words: i32,
+    chapters: Chapters,
+    comments: i32,
+    kudos: i32,
+    bookmarks: i32,
+    hits: i32,
+    summary: String,
+}
+
+fn tags(element: &ElementRef, selector: &str) -> Vec<String> {
+    select_all(element, selector)
+        .iter()
+        .filter_map(|a| a.text().next().map(str::to_string))
+        .collect()
+}
+
+impl WorkDetails {
+    pub(crate) fn from_element(element: &ElementRef, id: i64) -> Result<Self> {
+        let meta = select_next(element, "dl.work.meta")?;
+        let stats = select_next(&meta, "dl.stats")?;
+
+        let published = ao3_iso_dt_parse(&select_next_str(&stats, "dd.published")?);
+        let chapters = Chapters::parse(&select_string(&stats, "dd.chapters")?)?;
+        let updated = select_next_str(&stats, "dd.status")
+            .ok()
+            .map(|s| ao3_iso_dt_parse(&s));
+        let completed = match select_next_str(&stats, "dt.status") {
+            Ok(status) => updated.filter(|_| status.starts_with("Completed")),
+            // single chapter works don't show a status at all, and are complete when published
+            Err(_) => {
+                Some(published).filter(|_| matches!(chapters, Chapters::Known(a, b) if a == b))
+            }
+        };
+
+        let language = select_next(&meta, "dd.language")?;
+        let language_name = language.text().collect::<String>().trim().to_string();
+        let language = language
+            .value()
+            .attr("lang")
+            .map_or_else(|| language_name.clone(), str::to_string);
+
+        let preface = select_next(element, "div.preface")?;
+
+        Ok(WorkDetails {
+            id,
+            title: select_string(&preface, "h2.title")?.trim().to_string(),
+            authors: Authors::from_element(&select_next(&preface, "h3.byline")?)?,
+            rating: tags(&meta, "dd.rating.tags a.tag")
+                .iter()
+                .find_map(|r| Rating::from_label(r)),
+            warnings: tags(&meta, "dd.warning.tags a.tag")
+                .iter()
+                .filter_map(|w| Warning::from_label(w))
+                .collect(),
+            categories: tags(&meta, "dd.category.tags a.tag")
+                .iter()
+                .filter_map(|c| Category::from_label(c))
+                .collect(),
+            fandoms: tags(&meta, "dd.fandom.tags a.tag"),
+            relationships: tags(&meta, "dd.relationship.tags a.tag"),
+            characters: tags(&meta, "dd.character.tags a.tag"),
+            freeform: tags(&meta, "dd.freeform.tags a.tag"),
+            language,
+            language_name,
+            series: select_all(&meta, "dd.series span.position")
+                .iter()
+                .filter_map(|e| SeriesRef::from_position(e).ok())
+                .collect(),
+            published,
+            updated,
+            completed,
+            words: select_int(&stats, "dd.words").unwrap_or(0),
+            chapters,
+            comments: select_int(&stats, "dd.comments").unwrap_or(0),
+            kudos: select_int(&stats, "dd.kudos").unwrap_or(0),
+            bookmarks: select_int(&stats, "dd.bookmarks > a").unwrap_or(0),
+            hits: select_int(&stats, "dd.hits").unwrap_or(0),
+            summary: select_string(&preface, "div.summary > blockquote")
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub(crate) async fn new(session: &AuthorizedSession, id: i64) -> Result<WorkDetails> {
+        let html = session.get_work_page(id).await?;
+        Self::from_element(&html.root_element(), id)
+    }
+
+    /// The current [`revision`] of the work, which downloads are cached by.
+    pub(crate) fn revision(&self) -> String {
+        revision(self.updated.unwrap_or(self.published), &self.chapters)
+    }
+
+    /// What goes on the generated cover of the work.
+    pub(crate) fn cover(&self) -> Cover {
+        Cover {
+            title: self.title.clone(),
+            authors: self.authors.to_string(),
+            fandoms: self.fandoms.clone(),
+            rating: self.rating,
+            words: self.words,
+        }
+    }
+
+    /// What gets written into proxied EPUBs of the work.
+    pub(crate) fn epub_metadata(&self) -> EpubMetadata {
+        let required = self
+            .rating
+            .iter()
+            .map(Rating::label)
+            .chain(self.warnings.iter().map(Warning::label))
+            .chain(self.categories.iter().map(Category::label))
+            .map(str::to_string);
+        let subjects = required
+            .chain(
+                [
+                    &self.fandoms,
+                    &self.relationships,
+                    &self.characters,
+                    &self.freeform,
+                ]
+                .into_iter()
+                .flatten()
+                .cloned(),
+            )
+            .collect();
+
+        EpubMetadata {
+            title: self.title.clone(),
+            authors: self.authors.names(),
+            subjects,
+            series: self
+                .series
+                .first()
+                .map(|series| (series.name().to_string(), series.part())),
+            cover: self.cover(),
+        }
+    }
+}
+
+impl Filterable for WorkDetails {
+    fn filter_subject(&self) -> FilterSubject<'_> {
+        FilterSubject {
+            rating: self.rating,
+            warnings: &self.warnings,
+            fandoms: &self.fandoms,
+            relationships: &self.relationships,
+            freeforms: &self.freeform,
+            language: &self.language_name,
+            words: self.words,
+            completion: match self.completed {
+                Some(_) => Completion::Complete,
+                None => Completion::InProgress,
+            },
+        }
+    }
+}
+
+impl From<&WorkDetails> for OpdsEntry {
+    fn from(value: &WorkDetails) -> Self {
+        let mut entry = OpdsEntry::new(
+            format!("/works/{}", value.id),
+            value.updated.unwrap_or(value.published),
+            value.title.clone(),
+            None,
+            Some((&value.authors).into()),
+            Some(vec![OpdsLink::new(
+                OpdsLinkType::Entry,
+                OpdsLinkRel::ItSelf,
+                format!("/opds/v1.2/works/{}", value.id),
+            )]),
+        );
+        entry.links.extend(download_links(
+            value.id,
+            &value.title,
+            Some(&value.revision()),
+        ));
+        entry.links.extend(cover_links(value.id));
+        entry.published = Some(value.published.to_rfc3339());
+        entry.issued = Some(value.published.format("%Y-%m-%d").to_string());
+        entry.summary = Some(value.summary.clone());
+        entry.language = Some(value.language.clone());
+        entry.extent = Some(format!("{} words", value.words));
+        entry.completed = value.completed.map(|completed| completed.to_rfc3339());
+        entry.stats = Some(OpdsStats {
+            chapters: value.chapters.to_string(),
+            words: value.words,
+            comments: value.comments,
+            kudos: value.kudos,
+            bookmarks: value.bookmarks,
+            hits: value.hits,
+        });
+
+        entry.categories.extend(value.rating.map(|r| r.category()));
+        entry
+            .categories
+            .extend(value.warnings.iter().map(Warning::category));
+        entry
+            .categories
+            .extend(value.categories.iter().map(Category::category));
+        entry
+            .categories
+            .extend(tag_categories("fandom", &value.fandoms));
+        entry
+            .links
+            .extend(value.fandoms.iter().map(|fandom| tag_feed_link(fandom)));
+        entry
+            .categories
+            .extend(tag_categories("relationship", &value.relationships));
+        entry
+            .categories
+            .extend(tag_categories("character", &value.characters));
+        entry
+            .categories
+            .extend(tag_categories("freeform", &value.freeform));
+        entry
+            .categories
+            .extend(value.series.iter().map(SeriesRef::category));
+        entry
+            .links
+            .extend(value.series.iter().filter_map(SeriesRef::link));
+        entry
+            .categories
+            .push(value.filter_subject().completion.category());
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::WorkDetails;
+    use crate::{
+        ao3::required_tags::{Category, Rating, Warning},
+        opds::OpdsEntry,
+    };
+
+    const WORK: &str = r#"<html><body class="logged-in">
+<dl class="work meta group">
+  <dd class="rating tags"><ul><li><a class="tag" href="/tags/Teen">Teen And Up Audiences</a></li></ul></dd>
+  <dd class="warning tags"><ul><li><a class="tag">No Archive Warnings Apply</a></li></ul></dd>
+  <dd class="category tags"><ul><li><a class="tag">Gen</a></li></ul></dd>
+  <dd class="fandom tags"><ul><li><a class="tag">Good Omens (TV)</a></li></ul></dd>
+  <dd class="relationship tags"><ul></ul></dd>
+  <dd class="character tags"><ul><li><a class="tag">Aziraphale</a></li><li><a class="tag">Crowley</a></li></ul></dd>
+  <dd class="freeform tags"><ul><li><a class="tag">Fluff</a></li></ul></dd>
+  <dd class="language" lang="en">English</dd>
+  <dd class="series"><span class="series"><span class="position">Part 2 of <a href="/series/7">Bookshop</a></span></span></dd>
+  <dd class="stats"><dl class="stats">
+    <dt class="published">Published:</dt><dd class="published">2023-01-02</dd>
+    <dt class="status">Completed:</dt><dd class="status">2023-02-03</dd>
+    <dt class="words">Words:</dt><dd class="words">12,345</dd>
+    <dt class="chapters">Chapters:</dt><dd class="chapters">3/3</dd>
+    <dt class="kudos">Kudos:</dt><dd class="kudos">42</dd>
+    <dt class="hits">Hits:</dt><dd class="hits">1,000</dd>
+  </dl></dd>
+</dl>
+<div id="workskin"><div class="preface group">
+  <h2 class="title heading">A Title</h2>
+  <h3 class="byline heading"><a rel="author" href="/users/someone/pseuds/someone">someone</a></h3>
+  <div class="summary module"><h3 class="heading">Summary:</h3><blockquote class="userstuff"><p>The summary.</p></blockquote></div>
+</div></div>
+</body></html>"#;
+
+    #[test]
+    fn parses_work_page() {
+        let html = Html::parse_document(WORK);
+        let work = WorkDetails::from_element(&html.root_element(), 1).unwrap();
+        assert_eq!(
+            work.completed.map(|c| c.to_rfc3339()),
+            Some("2023-02-03T00:00:00+00:00".to_string())
+        );
+        assert_eq!(work.rating, Some(Rating::Teen));
+        assert_eq!(work.warnings, [Warning::NoneApply]);
+        assert_eq!(work.categories, [Category::Gen]);
+        assert_eq!(work.words, 12345);
+        assert_eq!(work.characters, ["Aziraphale", "Crowley"]);
+        assert_eq!(work.series.len(), 1);
+
+        let entry = OpdsEntry::from(&work).standalone();
+        let xml = quick_xml::se::to_string(&entry).unwrap();
+        assert!(xml.contains("<summary>The summary.</summary>"));
+        assert!(xml.contains(r##"<category scheme="https://archiveofourown.org/tags#series" term="Bookshop" label="Part 2 of Bookshop"/>"##));
+        assert!(xml.contains("<dc:issued>2023-01-02</dc:issued>"));
+        assert!(xml.contains("<ao3:completed>2023-02-03T00:00:00+00:00</ao3:completed>"));
+        assert!(xml.contains(r#"<ao3:stats chapters="3/3" words="12345" comments="0" kudos="42" bookmarks="0" hits="1000"/>"#));
+        assert!(xml.contains(r##"<category scheme="https://archiveofourown.org/tags#status" term="Complete Work" label="Complete Work"/>"##));
+        assert!(entry.content.is_none());
+    }
+}
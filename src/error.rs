@@ -0,0 +1,186 @@
+use poem::{
+    error::ResponseError,
+    http::{header, HeaderValue, StatusCode},
+    Response,
+};
+
+use crate::opds::{
+    v2::{Opds2Feed, OPDS2_FEED_TYPE},
+    OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType,
+};
+
+/// Everything that can go wrong while serving a feed.
+///
+/// The `ao3` module returns these wrapped in a [`color_eyre::Report`],
+/// they are recovered when converting the report back at the HTTP layer.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Not logged in to AO3: {0}")]
+    NotLoggedIn(String),
+    #[error("AO3 is rate limiting us, try again later")]
+    RateLimited {
+        /// Seconds from AO3's `Retry-After` header.
+        retry_after: Option<u64>,
+    },
+    #[error("AO3 is down or in maintenance")]
+    Ao3Unavailable,
+    #[error("Work {0} is restricted or has been deleted")]
+    WorkUnavailable(i64),
+    #[error("Could not parse AO3 page, no element found with {selector}")]
+    Parse { selector: String },
+    #[error("Bad request: {0}")]
+    BadInput(String),
+    #[error("Sign in to do this")]
+    Unauthorized,
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<color_eyre::Report> for Error {
+    fn from(value: color_eyre::Report) -> Self {
+        match value.downcast::<Error>() {
+            Ok(error) => error,
+            Err(report) => match report.downcast_ref::<reqwest::Error>() {
+                Some(e) if e.is_connect() || e.is_timeout() => Error::Ao3Unavailable,
+                _ => {
+                    // the report can hold AO3's markup and our internals, so it only goes to the log
+                    tracing::error!("{:?}", report);
+                    Error::Other("Something went wrong, see the server log".to_string())
+                }
+            },
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status(&self) -> StatusCode {
+        match self {
+            // this is our own AO3 login, not the client's
+            Error::NotLoggedIn(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::Ao3Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Error::WorkUnavailable(_) => StatusCode::NOT_FOUND,
+            Error::Parse { .. } => StatusCode::BAD_GATEWAY,
+            Error::BadInput(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Responds with a feed holding the error, so e-readers show something readable.
+    fn as_response(&self) -> Response {
+        self.response(
+            &OpdsLinkType::Navigation.to_string(),
+            quick_xml::se::to_string(&self.feed()).unwrap_or_else(|_| self.to_string()),
+        )
+    }
+}
+
+impl Error {
+    fn feed(&self) -> OpdsFeed {
+        OpdsFeed::new(
+            "error".to_string(),
+            "Error".to_string(),
+            Some(vec![OpdsLink::new(
+                OpdsLinkType::Navigation,
+                OpdsLinkRel::Start,
+                "/opds/v1.2/catalog".to_string(),
+            )]),
+            vec![OpdsEntry::new(
+                "error".to_string(),
+                chrono::Utc::now().into(),
+                self.status()
+                    .canonical_reason()
+                    .unwrap_or("Error")
+                    .to_string(),
+                Some(self.to_string()),
+                None,
+                None,
+            )],
+        )
+    }
+
+    /// Responds with an OPDS 2.0 feed holding the error, for the JSON feed routes.
+    pub fn as_json_response(&self) -> Response {
+        let feed = Opds2Feed::from(&self.feed());
+        self.response(
+            OPDS2_FEED_TYPE,
+            serde_json::to_string(&feed).unwrap_or_else(|_| self.to_string()),
+        )
+    }
+
+    fn response(&self, content_type: &str, body: String) -> Response {
+        let mut response = Response::builder()
+            .status(self.status())
+            .content_type(content_type)
+            .body(body);
+        if let Error::RateLimited {
+            retry_after: Some(seconds),
+        } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, (*seconds).into());
+        }
+        if let Error::Unauthorized = self {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="ao3-opds""#),
+            );
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        error::ResponseError,
+        http::{header, StatusCode},
+    };
+
+    use super::Error;
+
+    #[test]
+    fn recovers_typed_error_from_report() {
+        let report = color_eyre::Report::from(Error::RateLimited {
+            retry_after: Some(30),
+        });
+        let error = Error::from(report);
+        assert_eq!(error.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = error.as_response();
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    }
+
+    #[test]
+    fn asks_for_credentials() {
+        let response = Error::Unauthorized.as_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[test]
+    fn hides_reports_from_clients() {
+        let error = Error::from(color_eyre::eyre::eyre!("secret internals"));
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!error.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn reports_own_login_failure_as_unavailable() {
+        let response = Error::NotLoggedIn("Invalid username or password".to_string()).as_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[test]
+    fn serves_json_errors() {
+        let response = Error::Ao3Unavailable.as_json_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/opds+json"
+        );
+    }
+}
@@ -1,109 +1,1023 @@
-use color_eyre::{eyre::eyre, Result};
-use std::{env, num::NonZeroUsize, sync::Arc};
+use color_eyre::Result;
+use std::{collections::HashMap, env, future::Future, path::PathBuf, sync::Arc, time::Duration};
 
-use crate::ao3::{AuthorizedSession, HistoryPage, Session};
-use tokio::sync::Mutex;
+use crate::ao3::{
+    cover::{Cover, CoverSize},
+    download::{download_filename, parse_range, ByteRange},
+    epub::rewrite_epub,
+    tag_works_postfix,
+    utils::unescape_tag,
+    AuthorWorksPage, AuthorizedSession, BookmarkPage, ChapterLog, DownloadCache, DownloadFormat,
+    FilterConfig, HistoryPage, HistoryQuery, SearchPage, SeriesPage, Session, SubscriptionKind,
+    SubscriptionPage, TagWorksPage, Updates, Work, WorkDetails, WorkSearchQuery,
+};
+use crate::error::Error;
 
+use futures_util::{stream, StreamExt, TryStreamExt};
 use moka::future::Cache;
-use opds::{feed, OpdsFeed};
+use opds::{
+    v2::{Opds2Publication, OPDS2_FEED_TYPE, OPDS2_PUBLICATION_TYPE},
+    Opds2Feed, OpdsEntry, OpdsFeed, OpdsLinkType, OpenSearchDescription, OpenSearchUrl,
+};
 use poem::{
-    error::ResponseError,
     get, handler,
-    http::{HeaderMap, HeaderValue, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     listener::TcpListener,
-    middleware::AddData,
-    web::{Data, Query},
-    Endpoint, EndpointExt, IntoResponse, Response, Result as WebResult, Route, Server,
+    put,
+    web::{
+        headers::{authorization::Basic, Authorization},
+        Data, Path, Query, Redirect, TypedHeader,
+    },
+    Body, EndpointExt, IntoResponse, Request, Response, ResponseBuilder, Result as WebResult,
+    Route, Server,
 };
 use quick_xml::{se, Writer};
 use std::io::Cursor;
 
-use crate::opds::{OpdsEntry, OpdsLink, OpdsLinkRel, OpdsLinkType};
-
 mod ao3;
+mod error;
 mod opds;
 
 pub type XmlWriter = Writer<Cursor<Vec<u8>>>;
 pub type XmlResult = std::result::Result<(), quick_xml::Error>;
 
-#[derive(Debug, thiserror::Error)]
-#[error("{message}")]
-struct EyreError {
-    message: String,
+use serde::{Deserialize, Deserializer};
+
+/// How long history pages are cached, which is how late updates may show in the updates feed.
+const HISTORY_TTL: Duration = Duration::from_secs(10 * 60);
+/// How long work pages and the work lists of series, tags and authors are cached, which is how
+/// late a changed work gets a new revision and a fresh download.
+const PAGE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Downloads bigger than this, and EPUBs of unknown size, are streamed through without being
+/// cached or rewritten.
+const MAX_BUFFERED_DOWNLOAD: usize = 64 * 1024 * 1024;
+/// How many history pages are fetched from AO3 at once for the updates feed.
+const HISTORY_FETCHES: usize = 4;
+/// How many work pages are fetched from AO3 at once when filtering subscriptions.
+const WORK_FETCHES: usize = 4;
+
+fn headers(kind: OpdsLinkType) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "Content-Type",
+        HeaderValue::from_str(&kind.to_string()).expect("link types are valid header values"),
+    );
+    headers
+}
+
+/// The username sent with basic auth, which selects the user's filters.
+fn user(auth: &Option<TypedHeader<Authorization<Basic>>>) -> Option<&str> {
+    auth.as_ref().map(|TypedHeader(auth)| auth.username())
+}
+
+/// The link types of the download formats offered to the user's clients.
+fn link_types(
+    data: &Ao3Cache,
+    auth: &Option<TypedHeader<Authorization<Basic>>>,
+) -> Vec<OpdsLinkType> {
+    data.filters
+        .formats(user(auth))
+        .iter()
+        .map(DownloadFormat::link_type)
+        .collect()
 }
 
-impl From<color_eyre::Report> for EyreError {
-    fn from(value: color_eyre::Report) -> Self {
-        EyreError {
-            message: format!("{:?}", value),
+/// The serialisation used by a group of feed routes.
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    /// OPDS 1.2, Atom XML
+    Atom,
+    /// OPDS 2.0, JSON
+    Json,
+}
+
+impl FeedFormat {
+    /// The path the feed routes of this format are nested under.
+    fn root(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "/opds/v1.2",
+            FeedFormat::Json => "/opds/v2.0",
         }
     }
 }
 
-impl ResponseError for EyreError {
-    fn status(&self) -> StatusCode {
-        StatusCode::BAD_REQUEST
+fn render(
+    feed: &OpdsFeed,
+    kind: OpdsLinkType,
+    format: FeedFormat,
+) -> std::result::Result<(HeaderMap, String), Error> {
+    match format {
+        FeedFormat::Atom => Ok((
+            headers(kind),
+            se::to_string(feed).map_err(|e| Error::Other(format!("could not serialise: {}", e)))?,
+        )),
+        FeedFormat::Json => {
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", HeaderValue::from_static(OPDS2_FEED_TYPE));
+            Ok((
+                headers,
+                serde_json::to_string(&Opds2Feed::from(feed))
+                    .map_err(|e| Error::Other(format!("could not serialise: {}", e)))?,
+            ))
+        }
     }
 }
 
-use serde::Deserialize;
+/// Keeps what goes on the covers of the works in a feed, so the covers it links to are rendered
+/// without fetching each work.
+fn remember_covers<'a>(
+    data: &Ao3Cache,
+    works: impl IntoIterator<Item = &'a Work>,
+) -> impl Future<Output = ()> + '_ {
+    let covers: Vec<_> = works.into_iter().map(|w| (w.id(), w.cover())).collect();
+    async move {
+        for (id, metadata) in covers {
+            data.cover_data_cache.insert(id, metadata).await;
+        }
+    }
+}
 
-#[derive(Deserialize)]
-struct Pagination {
-    page: usize,
+#[handler]
+async fn history_feed(
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    Query(query): Query<HistoryQuery>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    let a = history_page(&data, page).await?;
+    remember_covers(&data, a.works().iter().map(|w| w.work())).await;
+    Ok(render(
+        &OpdsFeed::from((a, &query, &data.filters.filters(user(&auth), "history")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
 }
 
-fn headers() -> WebResult<HeaderMap> {
-    let mut headers = HeaderMap::new();
-    let header = "application/atom+xml;profile=opds-catalog;kind=navigation"
-        .parse()
-        .map_err(|_| EyreError::from(eyre!("Failed to parse header")))?;
-    headers.insert("Content-Type", header);
-    Ok(headers)
+#[handler]
+async fn updates_feed(
+    Data(data): Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    // walks the whole history, sharing the cached pages with the history feed
+    let first = history_page(data, 1).await?;
+    let mut pages = vec![first.clone()];
+    pages.extend(
+        stream::iter(2..=first.last_page())
+            .map(|page| history_page(data, page))
+            .buffered(HISTORY_FETCHES)
+            .try_collect::<Vec<_>>()
+            .await?,
+    );
+    for a in &pages {
+        remember_covers(data, a.works().iter().map(|w| w.work())).await;
+    }
+
+    let updates = Updates::new(&pages, &data.chapter_log);
+    // the feed is still right without the log, it only can't count new chapters after a restart
+    if let Err(report) = data.chapter_log.save().await {
+        tracing::warn!("could not save the chapter log: {:?}", report);
+    }
+    Ok(render(
+        &OpdsFeed::from((&updates, &data.filters.filters(user(&auth), "updates")))
+            .retain_acquisitions(&link_types(data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+/// A history page, from the history page cache or AO3.
+async fn history_page(data: &Ao3Cache, page: usize) -> Result<Arc<HistoryPage>, Error> {
+    // the page may expire at any time, so the one just inserted is kept
+    if let Some(a) = data.history_page_cache.get(&page) {
+        return Ok(a);
+    }
+    let a = Arc::new(HistoryPage::new(&data.session, page).await?);
+    data.history_page_cache.insert(page, a.clone()).await;
+    Ok(a)
 }
 
 #[handler]
-async fn history_feed(
-    Query(Pagination { page }): Query<Pagination>,
+async fn later_feed(
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    Query(query): Query<HistoryQuery>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    if !data.later_page_cache.contains_key(&page) {
+        let a = HistoryPage::later(&data.session, page)
+            .await
+            .map_err(Error::from)?;
+        data.later_page_cache.insert(page, Arc::new(a)).await;
+    }
+
+    let a = data
+        .later_page_cache
+        .get(&page)
+        .expect("should be unreachable because cache is populated beforehand");
+    remember_covers(&data, a.works().iter().map(|w| w.work())).await;
+    Ok(render(
+        &OpdsFeed::from((a, &query, &data.filters.filters(user(&auth), "later")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn bookmarks_feed(
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
     data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
 ) -> WebResult<(HeaderMap, String)> {
-    if !data.history_page_cache.contains_key(&page) {
-        let a = HistoryPage::new(&data.session, page)
+    if !data.bookmark_page_cache.contains_key(&page) {
+        let a = BookmarkPage::new(&data.session, page)
             .await
-            .map_err(EyreError::from)?;
-        data.history_page_cache.insert(page, Arc::new(a)).await;
+            .map_err(Error::from)?;
+        data.bookmark_page_cache.insert(page, Arc::new(a)).await;
     }
 
     let a = data
-        .history_page_cache
+        .bookmark_page_cache
         .get(&page)
         .expect("should be unreachable because cache is populated beforehand");
+    remember_covers(&data, a.bookmarks().iter().map(|b| b.work())).await;
+    Ok(render(
+        &OpdsFeed::from((a, &data.filters.filters(user(&auth), "bookmarks")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn series_feed(
+    Path(id): Path<i64>,
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    // the page may expire at any time, so the one just inserted is kept
+    let a = match data.series_page_cache.get(&(id, page)) {
+        Some(a) => a,
+        None => {
+            let a = Arc::new(
+                SeriesPage::new(&data.session, id, page)
+                    .await
+                    .map_err(Error::from)?,
+            );
+            data.series_page_cache.insert((id, page), a.clone()).await;
+            a
+        }
+    };
+    remember_covers(&data, a.works()).await;
+    Ok(render(
+        &OpdsFeed::from((a, &data.filters.filters(user(&auth), "series")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+#[derive(Deserialize)]
+struct AuthorQuery {
+    pseud: Option<String>,
+    #[serde(default = "first_page")]
+    page: usize,
+}
+
+#[handler]
+async fn author_works_feed(
+    Path(username): Path<String>,
+    Query(AuthorQuery { pseud, page }): Query<AuthorQuery>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    let key = (username, pseud, page);
+    let a = match data.author_works_page_cache.get(&key) {
+        Some(a) => a,
+        None => {
+            let a = Arc::new(
+                AuthorWorksPage::new(&data.session, key.0.clone(), key.1.clone(), page)
+                    .await
+                    .map_err(Error::from)?,
+            );
+            data.author_works_page_cache
+                .insert(key.clone(), a.clone())
+                .await;
+            a
+        }
+    };
+    remember_covers(&data, a.works()).await;
+    Ok(render(
+        &OpdsFeed::from((a, &data.filters.filters(user(&auth), "authors")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn browse_feed(Data(format): Data<&FeedFormat>) -> WebResult<(HeaderMap, String)> {
+    Ok(render(
+        &ao3::browse_feed(),
+        OpdsLinkType::Navigation,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn tag_works_feed(
+    req: &Request,
+    Path(tag): Path<String>,
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<Response> {
+    let tag = unescape_tag(&tag);
+    let query = WorkSearchQuery::from_query_string(req.uri().query().unwrap_or_default())
+        .map_err(|e| Error::BadInput(e.to_string()))?;
+    let key = (tag, query, page);
+    let a = match data.tag_works_page_cache.get(&key) {
+        Some(a) => a,
+        None => {
+            let a = Arc::new(
+                TagWorksPage::new(&data.session, &key.0, key.1.clone(), page)
+                    .await
+                    .map_err(Error::from)?,
+            );
+            data.tag_works_page_cache
+                .insert(key.clone(), a.clone())
+                .await;
+            a
+        }
+    };
+    remember_covers(&data, a.works()).await;
+    if a.tag() != key.0 {
+        let postfix = tag_works_postfix(a.tag(), &key.1);
+        let separator = if postfix.contains('?') { '&' } else { '?' };
+        return Ok(Redirect::see_other(format!(
+            "{}/{}{}page={}",
+            format.root(),
+            postfix,
+            separator,
+            page
+        ))
+        .into_response());
+    }
+    Ok(render(
+        &OpdsFeed::from((a, &data.filters.filters(user(&auth), "tags")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?
+    .into_response())
+}
+
+/// Checks the credentials of requests changing things on AO3 against `OPDS_PASSWORD`.
+fn authorize(
+    data: &Ao3Cache,
+    auth: &Option<TypedHeader<Authorization<Basic>>>,
+) -> Result<(), Error> {
+    match (&data.action_password, auth) {
+        (Some(password), Some(TypedHeader(auth))) if auth.password() == password => Ok(()),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+async fn set_later(
+    data: &Ao3Cache,
+    auth: &Option<TypedHeader<Authorization<Basic>>>,
+    id: i64,
+    later: bool,
+) -> WebResult<StatusCode> {
+    authorize(data, auth)?;
+    data.session
+        .mark_for_later(id, later)
+        .await
+        .map_err(Error::from)?;
+    // both views of the readings page show whether a work is marked
+    data.later_page_cache.invalidate_all();
+    data.history_page_cache.invalidate_all();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn mark_for_later(
+    Path(id): Path<i64>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> WebResult<StatusCode> {
+    set_later(&data, &auth, id, true).await
+}
+
+#[handler]
+async fn unmark_for_later(
+    Path(id): Path<i64>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> WebResult<StatusCode> {
+    set_later(&data, &auth, id, false).await
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    /// The work title, for the file name.
+    name: Option<String>,
+}
+
+/// Responds with a whole file, or the requested range of it.
+fn serve_bytes(builder: ResponseBuilder, data: Vec<u8>, range: Option<&str>) -> Response {
+    let len = data.len() as u64;
+    match range.and_then(|range| parse_range(range, len)) {
+        None => builder.status(StatusCode::OK).body(data),
+        Some(ByteRange::Satisfiable(range)) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            )
+            .body(data[range.start as usize..range.end as usize].to_vec()),
+        Some(ByteRange::Unsatisfiable) => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .finish(),
+    }
+}
+
+/// Streams a download from AO3 through our session, so restricted works can be downloaded too.
+///
+/// Needs the same password as the other actions, since anyone could use our account otherwise.
+/// Downloads are kept in the download cache by the work's current revision, and any cached
+/// revision is served while AO3 is unavailable.
+#[handler]
+async fn download(
+    req: &Request,
+    Path((id, file)): Path<(i64, String)>,
+    Query(DownloadQuery { name }): Query<DownloadQuery>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> WebResult<Response> {
+    authorize(&data, &auth)?;
+    let format = file
+        .strip_prefix("download.")
+        .and_then(DownloadFormat::from_extension)
+        .ok_or_else(|| Error::BadInput(format!("unknown download: {}", file)))?;
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok());
+
+    let filename = download_filename(name.as_deref().unwrap_or(&id.to_string()), format);
+    let builder = Response::builder()
+        .content_type(format.link_type().to_string())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    // the revision is looked up rather than taken from the link, which may be stale
+    let rev = match work_details(&data, id).await {
+        Ok(work) => work.revision(),
+        Err(error) => return serve_latest(&data, id, format, error, builder, range).await,
+    };
+    if let Some(cached) = data
+        .downloads
+        .get(id, format, &rev)
+        .await
+        .map_err(Error::from)?
+    {
+        return Ok(serve_bytes(builder, cached, range));
+    }
+
+    let rewrite = data.rewrite_epubs && format == DownloadFormat::Epub;
+    // rewritten downloads are fetched whole and ranges are cut out here, other ranges are
+    // passed on and not cached
+    let upstream_range = if rewrite { None } else { range };
+    let res = match data.session.get_download(id, format, upstream_range).await {
+        Ok(res) => res,
+        Err(report) => {
+            return serve_latest(&data, id, format, Error::from(report), builder, range).await
+        }
+    };
+    let small = res
+        .content_length()
+        .is_some_and(|len| len <= MAX_BUFFERED_DOWNLOAD as u64);
+
+    if rewrite && small && res.status() == StatusCode::OK {
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| Error::from(color_eyre::Report::new(e)))?
+            .to_vec();
+        let body = rewrite_download(&data, id, body).await?;
+        data.downloads
+            .insert(id, format, &rev, &body)
+            .await
+            .map_err(Error::from)?;
+        return Ok(serve_bytes(builder, body, range));
+    }
+
+    let mut builder = builder.status(res.status());
+    for name in [header::CONTENT_LENGTH, header::CONTENT_RANGE] {
+        if let Some(value) = res.headers().get(&name) {
+            builder = builder.header(name, value.clone());
+        }
+    }
+    // big EPUBs aren't cached either, so the file kept is always the rewritten one
+    if !rewrite && range.is_none() && res.status() == StatusCode::OK {
+        return Ok(builder.body(stream_into_cache(&data, id, format, rev, res)));
+    }
+    Ok(builder.body(Body::from_bytes_stream(
+        res.bytes_stream().map_err(std::io::Error::other),
+    )))
+}
+
+/// Passes a whole download on as it arrives, and caches it once it is complete.
+///
+/// Downloads over [`MAX_BUFFERED_DOWNLOAD`] are only passed on.
+fn stream_into_cache(
+    data: &Ao3Cache,
+    id: i64,
+    format: DownloadFormat,
+    rev: String,
+    res: reqwest::Response,
+) -> Body {
+    let downloads = data.downloads.clone();
+    let chunks = Box::pin(res.bytes_stream());
+    let state = (chunks, Some(Vec::new()), downloads, rev);
+    Body::from_bytes_stream(stream::unfold(
+        state,
+        move |(mut chunks, mut file, downloads, rev)| async move {
+            match chunks.next().await {
+                Some(Ok(chunk)) => {
+                    file = file.filter(|file| file.len() + chunk.len() <= MAX_BUFFERED_DOWNLOAD);
+                    if let Some(file) = &mut file {
+                        file.extend_from_slice(&chunk);
+                    }
+                    Some((Ok(chunk), (chunks, file, downloads, rev)))
+                }
+                Some(Err(e)) => Some((
+                    Err(std::io::Error::other(e)),
+                    (chunks, None, downloads, rev),
+                )),
+                None => {
+                    if let Some(file) = file {
+                        if let Err(report) = downloads.insert(id, format, &rev, &file).await {
+                            tracing::warn!(
+                                "could not cache the download of work {}: {:?}",
+                                id,
+                                report
+                            );
+                        }
+                    }
+                    None
+                }
+            }
+        },
+    ))
+}
+
+/// Serves the latest cached revision of a download while AO3 can't be reached, or else the error.
+async fn serve_latest(
+    data: &Ao3Cache,
+    id: i64,
+    format: DownloadFormat,
+    error: Error,
+    builder: ResponseBuilder,
+    range: Option<&str>,
+) -> WebResult<Response> {
+    if let Error::Ao3Unavailable | Error::RateLimited { .. } = error {
+        if let Some(cached) = data
+            .downloads
+            .latest(id, format)
+            .await
+            .map_err(Error::from)?
+        {
+            return Ok(serve_bytes(builder, cached, range));
+        }
+    }
+    Err(error.into())
+}
+
+/// Writes the cover and metadata of the work into an EPUB from AO3.
+async fn rewrite_download(data: &Ao3Cache, id: i64, epub: Vec<u8>) -> WebResult<Vec<u8>> {
+    let metadata = work_details(data, id).await?.epub_metadata();
+    // the whole archive is read and written again, which is too slow for the async runtime
+    let (epub, rewritten) = tokio::task::spawn_blocking(move || {
+        let rewritten = rewrite_epub(&epub, &metadata);
+        (epub, rewritten)
+    })
+    .await
+    .map_err(|e| Error::from(color_eyre::Report::new(e)))?;
+
+    // AO3's own file is still better than no file when it can't be rewritten
+    match rewritten {
+        Ok(rewritten) => Ok(rewritten),
+        Err(report) => {
+            tracing::warn!("could not rewrite the EPUB of work {}: {:?}", id, report);
+            Ok(epub)
+        }
+    }
+}
+
+async fn set_pinned(
+    data: &Ao3Cache,
+    auth: &Option<TypedHeader<Authorization<Basic>>>,
+    id: i64,
+    pinned: bool,
+) -> WebResult<StatusCode> {
+    authorize(data, auth)?;
+    data.downloads.pin(id, pinned).await.map_err(Error::from)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+async fn pin(
+    Path(id): Path<i64>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> WebResult<StatusCode> {
+    set_pinned(&data, &auth, id, true).await
+}
+
+#[handler]
+async fn unpin(
+    Path(id): Path<i64>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+) -> WebResult<StatusCode> {
+    set_pinned(&data, &auth, id, false).await
+}
+
+/// Renders the cover of a work from its metadata, or takes it from the cover cache.
+///
+/// The metadata is usually kept from the feed linking to the cover, the work is only fetched
+/// when it has been forgotten.
+async fn serve_cover(data: &Ao3Cache, id: i64, size: CoverSize) -> WebResult<Response> {
+    let metadata = match data.cover_data_cache.get(&id) {
+        Some(metadata) => metadata,
+        None => {
+            let metadata = work_details(data, id).await?.cover();
+            data.cover_data_cache.insert(id, metadata.clone()).await;
+            metadata
+        }
+    };
+
+    // rendered by what is on the cover, so a changed title or author gets a new one
+    let key = (metadata, size);
+    let jpeg = match data.cover_cache.get(&key) {
+        Some(jpeg) => jpeg,
+        None => {
+            let metadata = key.0.clone();
+            let jpeg = tokio::task::spawn_blocking(move || metadata.render(size))
+                .await
+                .map_err(|e| Error::from(color_eyre::Report::new(e)))?
+                .map_err(Error::from)?;
+            let jpeg = Arc::new(jpeg);
+            data.cover_cache.insert(key, jpeg.clone()).await;
+            jpeg
+        }
+    };
+    Ok(Response::builder()
+        .content_type(OpdsLinkType::Image.to_string())
+        .body(jpeg.as_ref().clone()))
+}
+
+#[handler]
+async fn cover(Path(id): Path<i64>, data: Data<&Ao3Cache>) -> WebResult<Response> {
+    serve_cover(&data, id, CoverSize::Full).await
+}
+
+#[handler]
+async fn thumbnail(Path(id): Path<i64>, data: Data<&Ao3Cache>) -> WebResult<Response> {
+    serve_cover(&data, id, CoverSize::Thumbnail).await
+}
+
+#[handler]
+async fn work_entry(
+    Path(id): Path<i64>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    let a = work_details(&data, id).await?;
+    data.cover_data_cache.insert(id, a.cover()).await;
+    let mut entry = OpdsEntry::from(a.as_ref()).standalone();
+    entry.retain_acquisitions(&link_types(&data, &auth));
+    match format {
+        FeedFormat::Atom => Ok((
+            headers(OpdsLinkType::Entry),
+            se::to_string(&entry)
+                .map_err(|e| Error::Other(format!("could not serialise: {}", e)))?,
+        )),
+        FeedFormat::Json => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "Content-Type",
+                HeaderValue::from_static(OPDS2_PUBLICATION_TYPE),
+            );
+            Ok((
+                headers,
+                serde_json::to_string(&Opds2Publication::from(&entry))
+                    .map_err(|e| Error::Other(format!("could not serialise: {}", e)))?,
+            ))
+        }
+    }
+}
+
+#[handler]
+async fn catalog_feed(Data(format): Data<&FeedFormat>) -> WebResult<(HeaderMap, String)> {
+    Ok(render(
+        &ao3::catalog_feed(),
+        OpdsLinkType::Navigation,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn opensearch_description() -> WebResult<(HeaderMap, String)> {
+    let description = OpenSearchDescription::new(
+        "AO3".to_string(),
+        "Search works on Archive of Our Own".to_string(),
+        vec![OpenSearchUrl::new(
+            OpdsLinkType::Acquisition,
+            "/opds/v1.2/search?q={searchTerms}&page={startPage?}".to_string(),
+        )],
+    );
     Ok((
-        headers()?,
-        se::to_string::<OpdsFeed>(&a.into())
-            .map_err(|_| EyreError::from(eyre!("could not serialise")))?,
+        headers(OpdsLinkType::Search),
+        se::to_string(&description)
+            .map_err(|e| Error::Other(format!("could not serialise: {}", e)))?,
     ))
 }
 
+fn first_page() -> usize {
+    1
+}
+
+/// Reads the page number, treating an empty `page=` as the first page.
+///
+/// OpenSearch clients leave optional template parameters like `{startPage?}` empty.
+fn optional_page<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<usize, D::Error> {
+    let page = String::deserialize(deserializer)?;
+    if page.is_empty() {
+        return Ok(first_page());
+    }
+    page.parse().map_err(serde::de::Error::custom)
+}
+
+#[derive(Deserialize)]
+struct OptionalPagination {
+    #[serde(default = "first_page", deserialize_with = "optional_page")]
+    page: usize,
+}
+
+#[handler]
+async fn search_feed(
+    req: &Request,
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    let query = WorkSearchQuery::from_query_string(req.uri().query().unwrap_or_default())
+        .map_err(|e| Error::BadInput(e.to_string()))?;
+    let key = (query, page);
+    if !data.search_page_cache.contains_key(&key) {
+        let a = SearchPage::new(&data.session, &key.0, page)
+            .await
+            .map_err(Error::from)?;
+        data.search_page_cache
+            .insert(key.clone(), Arc::new(a))
+            .await;
+    }
+
+    let a = data
+        .search_page_cache
+        .get(&key)
+        .expect("should be unreachable because cache is populated beforehand");
+    remember_covers(&data, a.works()).await;
+    Ok(render(
+        &OpdsFeed::from((a, &data.filters.filters(user(&auth), "search")))
+            .retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Acquisition,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn subscriptions_feed(Data(format): Data<&FeedFormat>) -> WebResult<(HeaderMap, String)> {
+    Ok(render(
+        &SubscriptionKind::navigation_feed(),
+        OpdsLinkType::Navigation,
+        *format,
+    )?)
+}
+
+#[handler]
+async fn subscriptions_kind_feed(
+    Path(kind): Path<SubscriptionKind>,
+    Query(OptionalPagination { page }): Query<OptionalPagination>,
+    data: Data<&Ao3Cache>,
+    auth: Option<TypedHeader<Authorization<Basic>>>,
+    Data(format): Data<&FeedFormat>,
+) -> WebResult<(HeaderMap, String)> {
+    if !data.subscription_page_cache.contains_key(&(kind, page)) {
+        let a = SubscriptionPage::new(&data.session, kind, page)
+            .await
+            .map_err(Error::from)?;
+        data.subscription_page_cache
+            .insert((kind, page), Arc::new(a))
+            .await;
+    }
+
+    let a = data
+        .subscription_page_cache
+        .get(&(kind, page))
+        .expect("should be unreachable because cache is populated beforehand");
+
+    let filters = data.filters.filters(user(&auth), "subscriptions");
+    // the page only names the works, so they are only fetched when there is something to filter
+    let mut details = HashMap::new();
+    if !filters.is_empty() {
+        let ids: Vec<_> = a
+            .subscriptions()
+            .iter()
+            .filter_map(|s| s.work_id())
+            .collect();
+        let data: &Ao3Cache = &data;
+        let mut works = stream::iter(ids)
+            .map(|id| async move { (id, work_details(data, id).await) })
+            .buffer_unordered(WORK_FETCHES);
+        while let Some((id, work)) = works.next().await {
+            // works which couldn't be fetched are kept, rather than failing the whole feed
+            match work {
+                Ok(work) => {
+                    details.insert(id, work);
+                }
+                Err(e) => tracing::warn!("Failed to fetch work {} for filtering: {}", id, e),
+            }
+        }
+    }
+    Ok(render(
+        &OpdsFeed::from((a, &filters, &details)).retain_acquisitions(&link_types(&data, &auth)),
+        OpdsLinkType::Navigation,
+        *format,
+    )?)
+}
+
+/// The details of a work, from the work cache or AO3.
+async fn work_details(data: &Ao3Cache, id: i64) -> Result<Arc<WorkDetails>, Error> {
+    // the work may expire at any time, so the one just inserted is kept
+    if let Some(work) = data.work_cache.get(&id) {
+        return Ok(work);
+    }
+    let work = Arc::new(WorkDetails::new(&data.session, id).await?);
+    data.work_cache.insert(id, work.clone()).await;
+    Ok(work)
+}
+
 #[derive(Clone)]
 struct Ao3Cache {
     session: AuthorizedSession,
     history_page_cache: Cache<usize, Arc<HistoryPage>>,
+    later_page_cache: Cache<usize, Arc<HistoryPage>>,
+    chapter_log: Arc<ChapterLog>,
+    downloads: Arc<DownloadCache>,
+    rewrite_epubs: bool,
+    bookmark_page_cache: Cache<usize, Arc<BookmarkPage>>,
+    subscription_page_cache: Cache<(SubscriptionKind, usize), Arc<SubscriptionPage>>,
+    search_page_cache: Cache<(WorkSearchQuery, usize), Arc<SearchPage>>,
+    work_cache: Cache<i64, Arc<WorkDetails>>,
+    cover_cache: Cache<(Cover, CoverSize), Arc<Vec<u8>>>,
+    cover_data_cache: Cache<i64, Cover>,
+    series_page_cache: Cache<(i64, usize), Arc<SeriesPage>>,
+    author_works_page_cache: Cache<(String, Option<String>, usize), Arc<AuthorWorksPage>>,
+    tag_works_page_cache: Cache<(String, WorkSearchQuery, usize), Arc<TagWorksPage>>,
+    filters: Arc<FilterConfig>,
+    /// The password for requests changing things on AO3, which are refused without one.
+    action_password: Option<String>,
+}
+
+/// Serves our errors as OPDS 2.0 on the JSON routes, instead of the Atom feed they default to.
+async fn json_error(error: poem::Error) -> Response {
+    match error.downcast_ref::<Error>() {
+        Some(error) => error.as_json_response(),
+        None => error.into_response(),
+    }
+}
+
+/// The feeds, served once per [`FeedFormat`].
+fn feed_routes() -> Route {
+    Route::new()
+        .at("/catalog", get(catalog_feed))
+        .at("/history", get(history_feed))
+        .at("/bookmarks", get(bookmarks_feed))
+        .at("/search", get(search_feed))
+        .at("/search/opensearch.xml", get(opensearch_description))
+        .at("/subscriptions", get(subscriptions_feed))
+        .at("/subscriptions/:kind", get(subscriptions_kind_feed))
+        .at("/later", get(later_feed))
+        .at("/updates", get(updates_feed))
+        .at("/works/:id", get(work_entry))
+        .at(
+            "/works/:id/later",
+            put(mark_for_later).delete(unmark_for_later),
+        )
+        .at("/works/:id/pin", put(pin).delete(unpin))
+        .at("/works/:id/cover.jpg", get(cover))
+        .at("/works/:id/thumbnail.jpg", get(thumbnail))
+        .at("/works/:id/:file", get(download))
+        .at("/series/:id", get(series_feed))
+        .at("/authors/:user/works", get(author_works_feed))
+        .at("/browse", get(browse_feed))
+        .at("/tags/:tag/works", get(tag_works_feed))
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv()?;
     color_eyre::install()?;
-    let session = Session::new()?;
-    let session = session.login("laundmo", &env::var("AO3_PW")?).await?;
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+    let cookie_file = env::var("AO3_COOKIE_FILE").unwrap_or_else(|_| ".ao3-cookies".to_string());
+    // kept next to the cookies, since both belong to the AO3 account
+    let chapter_log = PathBuf::from(&cookie_file).with_file_name(".ao3-chapters.json");
+    let chapter_log = ChapterLog::open(chapter_log).await?;
+    let session = Session::new(cookie_file.into())?;
+    let session = session.resume("laundmo", &env::var("AO3_PW")?).await?;
+    let filters = env::var("AO3_FILTERS").unwrap_or_else(|_| "filters.toml".to_string());
+    let filters = FilterConfig::load(filters.as_ref())?;
+    let downloads = env::var("AO3_DOWNLOAD_CACHE").unwrap_or_else(|_| ".ao3-downloads".to_string());
+    let downloads_mb = match env::var("AO3_DOWNLOAD_CACHE_MB") {
+        Ok(mb) => mb.parse()?,
+        Err(_) => 1024,
+    };
+    let downloads = DownloadCache::open(downloads.into(), downloads_mb * 1024 * 1024)?;
+    let rewrite_epubs = match env::var("AO3_REWRITE_EPUBS") {
+        Ok(rewrite) => rewrite.parse()?,
+        Err(_) => false,
+    };
     let cache = Ao3Cache {
         session,
-        history_page_cache: Cache::new(100),
+        // expire, so the updates feed sees works updated since the pages were fetched
+        history_page_cache: Cache::builder()
+            .max_capacity(100)
+            .time_to_live(HISTORY_TTL)
+            .build(),
+        later_page_cache: Cache::new(100),
+        chapter_log: Arc::new(chapter_log),
+        downloads: Arc::new(downloads),
+        rewrite_epubs,
+        bookmark_page_cache: Cache::new(100),
+        subscription_page_cache: Cache::new(100),
+        search_page_cache: Cache::new(100),
+        work_cache: Cache::builder()
+            .max_capacity(100)
+            .time_to_live(PAGE_TTL)
+            .build(),
+        cover_cache: Cache::new(500),
+        // expire, so covers which no feed links to anymore are not kept from old work pages
+        cover_data_cache: Cache::builder()
+            .max_capacity(5000)
+            .time_to_live(PAGE_TTL)
+            .build(),
+        series_page_cache: Cache::builder()
+            .max_capacity(100)
+            .time_to_live(PAGE_TTL)
+            .build(),
+        author_works_page_cache: Cache::builder()
+            .max_capacity(100)
+            .time_to_live(PAGE_TTL)
+            .build(),
+        tag_works_page_cache: Cache::builder()
+            .max_capacity(100)
+            .time_to_live(PAGE_TTL)
+            .build(),
+        filters: Arc::new(filters),
+        action_password: env::var("OPDS_PASSWORD").ok(),
     };
 
     let app = Route::new()
-        .at("/opds/v1.2/history", get(history_feed))
+        .nest("/opds/v1.2", feed_routes().data(FeedFormat::Atom))
+        .nest(
+            "/opds/v2.0",
+            feed_routes()
+                .data(FeedFormat::Json)
+                .catch_all_error(json_error),
+        )
         .data(cache);
     Server::new(TcpListener::bind("0.0.0.0:3000"))
         .name("hello-world")
@@ -111,3 +1025,26 @@ async fn main() -> Result<()> {
         .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use poem::{http::Uri, web::Query, FromRequest, Request};
+
+    use super::OptionalPagination;
+
+    async fn page(uri: &'static str) -> Option<usize> {
+        let req = Request::builder().uri(Uri::from_static(uri)).finish();
+        Query::<OptionalPagination>::from_request_without_body(&req)
+            .await
+            .ok()
+            .map(|Query(pagination)| pagination.page)
+    }
+
+    #[tokio::test]
+    async fn treats_empty_page_as_first() {
+        assert_eq!(page("/search?q=a").await, Some(1));
+        assert_eq!(page("/search?q=a&page=").await, Some(1));
+        assert_eq!(page("/search?q=a&page=3").await, Some(3));
+        assert_eq!(page("/search?q=a&page=x").await, None);
+    }
+}
@@ -4,6 +4,7 @@ use serde::Serialize;
 #[derive(Debug, Serialize)]
 pub struct StumpAuthor {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub uri: Option<String>,
 }
 
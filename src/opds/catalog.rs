@@ -0,0 +1,87 @@
+use chrono::Utc;
+
+use super::{OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType};
+
+/// A feed listed in a navigation feed, such as the root catalog.
+#[derive(Debug, Clone, Copy)]
+pub struct CatalogEntry {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    pub href: &'static str,
+    /// The kind of the linked feed, usually [`OpdsLinkType::Navigation`] or [`OpdsLinkType::Acquisition`].
+    pub kind: OpdsLinkType,
+}
+
+impl From<&CatalogEntry> for OpdsEntry {
+    fn from(value: &CatalogEntry) -> Self {
+        OpdsEntry::new(
+            value.id.to_string(),
+            Utc::now().into(),
+            value.title.to_string(),
+            Some(value.description.to_string()),
+            None,
+            Some(vec![OpdsLink::new(
+                value.kind,
+                match value.kind {
+                    OpdsLinkType::Search => OpdsLinkRel::Search,
+                    _ => OpdsLinkRel::Subsection,
+                },
+                value.href.to_string(),
+            )]),
+        )
+    }
+}
+
+impl OpdsFeed {
+    /// Builds a navigation feed out of a registry of feeds.
+    pub fn navigation(
+        id: &str,
+        title: &str,
+        href_postfix: &str,
+        registry: &[CatalogEntry],
+    ) -> Self {
+        OpdsFeed::new(
+            id.to_string(),
+            title.to_string(),
+            Some(vec![
+                OpdsLink::new(
+                    OpdsLinkType::Navigation,
+                    OpdsLinkRel::ItSelf,
+                    format!("/opds/v1.2/{}", href_postfix),
+                ),
+                OpdsLink::new(
+                    OpdsLinkType::Navigation,
+                    OpdsLinkRel::Start,
+                    "/opds/v1.2/catalog".into(),
+                ),
+            ]),
+            registry.iter().map(OpdsEntry::from).collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opds::{CatalogEntry, OpdsFeed, OpdsLinkType};
+
+    #[test]
+    fn links_subsections_with_kind() {
+        let feed = OpdsFeed::navigation(
+            "catalog",
+            "Catalog",
+            "catalog",
+            &[CatalogEntry {
+                id: "history",
+                title: "History",
+                description: "History",
+                href: "/opds/v1.2/history?page=1",
+                kind: OpdsLinkType::Acquisition,
+            }],
+        );
+        let xml = quick_xml::se::to_string(&feed).unwrap();
+        assert!(xml.contains(
+            r#"type="application/atom+xml;profile=opds-catalog;kind=acquisition" rel="subsection" href="/opds/v1.2/history?page=1""#
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+use serde::Serialize;
+
+/// An Atom category, used for tags and other classifications of an entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpdsCategory {
+    #[serde(rename = "@scheme")]
+    pub scheme: String,
+    #[serde(rename = "@term")]
+    pub term: String,
+    #[serde(rename = "@label", skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl OpdsCategory {
+    pub fn new(scheme: String, term: String, label: Option<String>) -> Self {
+        Self {
+            scheme,
+            term,
+            label,
+        }
+    }
+}
@@ -1,15 +1,77 @@
-use super::{link::OpdsLink, StumpAuthor};
+use super::{
+    link::{OpdsLink, OpdsLinkRel, OpdsLinkType},
+    OpdsCategory, StumpAuthor,
+};
 use chrono::{DateTime, FixedOffset};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
+#[serde(rename = "entry")]
 pub struct OpdsEntry {
-    id: String,
-    updated: String,
-    title: String,
-    content: Option<String>,
-    authors: Option<Vec<StumpAuthor>>,
-    links: Vec<OpdsLink>,
+    /// Only set on standalone entry documents, in feeds the namespaces are declared on the feed.
+    #[serde(rename = "@xmlns", skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "@xmlns:dc", skip_serializing_if = "Option::is_none")]
+    pub xmlns_dc: Option<String>,
+    #[serde(rename = "@xmlns:ao3", skip_serializing_if = "Option::is_none")]
+    pub xmlns_ao3: Option<String>,
+    pub id: String,
+    pub updated: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub content: Option<String>,
+    #[serde(rename = "author")]
+    pub authors: Option<Vec<StumpAuthor>>,
+    #[serde(rename = "category", skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<OpdsCategory>,
+    #[serde(rename = "dc:language", skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(rename = "dc:issued", skip_serializing_if = "Option::is_none")]
+    pub issued: Option<String>,
+    #[serde(rename = "dc:extent", skip_serializing_if = "Option::is_none")]
+    pub extent: Option<String>,
+    /// When the work was completed, which `updated` is for complete works too.
+    #[serde(rename = "ao3:completed", skip_serializing_if = "Option::is_none")]
+    pub completed: Option<String>,
+    #[serde(rename = "ao3:stats", skip_serializing_if = "Option::is_none")]
+    pub stats: Option<OpdsStats>,
+    #[serde(rename = "ao3:visit", skip_serializing_if = "Option::is_none")]
+    pub visit: Option<OpdsVisit>,
+    #[serde(rename = "link")]
+    pub links: Vec<OpdsLink>,
+}
+
+/// When and how often the entry was read, in the `ao3` namespace declared on feeds.
+#[derive(Debug, Serialize)]
+pub struct OpdsVisit {
+    #[serde(rename = "@last")]
+    pub last: String,
+    #[serde(rename = "@count")]
+    pub count: i32,
+    /// What changed since, like `latest`, `minor` or `updated`.
+    #[serde(rename = "@changed")]
+    pub changed: String,
+}
+
+/// The counts AO3 shows under a work, in the `ao3` namespace.
+#[derive(Debug, Serialize)]
+pub struct OpdsStats {
+    /// Posted and planned chapters, like `3/5` or `3/?`.
+    #[serde(rename = "@chapters")]
+    pub chapters: String,
+    #[serde(rename = "@words")]
+    pub words: i32,
+    #[serde(rename = "@comments")]
+    pub comments: i32,
+    #[serde(rename = "@kudos")]
+    pub kudos: i32,
+    #[serde(rename = "@bookmarks")]
+    pub bookmarks: i32,
+    #[serde(rename = "@hits")]
+    pub hits: i32,
 }
 
 impl OpdsEntry {
@@ -21,21 +83,58 @@ impl OpdsEntry {
         authors: Option<Vec<StumpAuthor>>,
         links: Option<Vec<OpdsLink>>,
     ) -> Self {
-        let links = match links {
-            Some(links) => links,
-            None => vec![],
-        };
+        let links = links.unwrap_or_default();
 
         Self {
+            xmlns: None,
+            xmlns_dc: None,
+            xmlns_ao3: None,
             id,
             updated: updated.to_rfc3339(),
+            published: None,
             title,
+            summary: None,
             content,
             authors,
+            categories: Vec::new(),
+            language: None,
+            issued: None,
+            extent: None,
+            completed: None,
+            stats: None,
+            visit: None,
             links,
         }
     }
 
+    /// Declares the namespaces on the entry itself, for serving it as an entry document.
+    pub fn standalone(mut self) -> Self {
+        self.xmlns = Some("http://www.w3.org/2005/Atom".to_string());
+        self.xmlns_dc = Some("http://purl.org/dc/terms/".to_string());
+        self.xmlns_ao3 = Some("urn:x-ao3-opds:reading".to_string());
+        self
+    }
+
+    /// Drops acquisition links whose type is not one of the given types.
+    pub fn retain_acquisitions(&mut self, link_types: &[OpdsLinkType]) {
+        let acquisition = OpdsLinkRel::Acquisition.to_string();
+        let link_types: Vec<String> = link_types.iter().map(OpdsLinkType::to_string).collect();
+        self.links
+            .retain(|link| link.rel != acquisition || link_types.contains(&link.link_type));
+    }
+
+    /// Appends a line to the content, creating it if there is none yet.
+    pub fn push_content(&mut self, line: &str) {
+        match &mut self.content {
+            Some(content) => {
+                content.push('\n');
+                content.push_str(line);
+            }
+            None => self.content = Some(line.to_string()),
+        }
+    }
+
+    #[allow(dead_code)]
     fn get_content(&self) -> Option<String> {
         self.content
             .as_ref()
@@ -12,9 +12,17 @@ pub struct OpdsFeed {
     pub xmlns: String,
     #[serde(rename = "@xmlns:opds")]
     pub xmlns_opds: String,
+    #[serde(rename = "@xmlns:dc")]
+    pub xmlns_dc: String,
+    #[serde(rename = "@xmlns:thr")]
+    pub xmlns_thr: String,
+    #[serde(rename = "@xmlns:ao3")]
+    pub xmlns_ao3: String,
     pub updated: String,
     pub id: String,
     pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
     #[serde(rename = "link")]
     pub links: Option<Vec<OpdsLink>>,
     #[serde(rename = "entry")]
@@ -31,14 +39,38 @@ impl OpdsFeed {
         Self {
             xmlns: "http://www.w3.org/2005/Atom".to_string(),
             xmlns_opds: "http://opds-spec.org/2010/catalog".to_string(),
+            xmlns_dc: "http://purl.org/dc/terms/".to_string(),
+            xmlns_thr: "http://purl.org/syndication/thread/1.0".to_string(),
+            xmlns_ao3: "urn:x-ao3-opds:reading".to_string(),
             updated: Utc::now().to_rfc3339(),
             id,
             title,
+            subtitle: None,
             entries,
             links,
         }
     }
 
+    /// Notes in the subtitle how many entries were hidden by filters.
+    pub fn with_hidden(mut self, hidden: usize) -> Self {
+        if hidden > 0 {
+            let note = format!("{} entries hidden by filters", hidden);
+            self.subtitle = Some(match self.subtitle {
+                Some(subtitle) => format!("{}\n{}", subtitle, note),
+                None => note,
+            });
+        }
+        self
+    }
+
+    /// Drops acquisition links whose type is not one of the given types from all entries.
+    pub fn retain_acquisitions(mut self, link_types: &[OpdsLinkType]) -> Self {
+        for entry in &mut self.entries {
+            entry.retain_acquisitions(link_types);
+        }
+        self
+    }
+
     pub fn paginated<T>(
         id: &str,
         title: &str,
@@ -86,11 +118,14 @@ where
             ),
         ];
 
+        // the postfix may already carry a query, like search terms
+        let separator = if href_postfix.contains('?') { '&' } else { '?' };
+
         if has_previous {
             links.push(OpdsLink::new(
                 OpdsLinkType::Navigation,
                 OpdsLinkRel::Previous,
-                format!("/opds/v1.2/{}?page={}", href_postfix, page - 1),
+                format!("/opds/v1.2/{}{}page={}", href_postfix, separator, page - 1),
             ));
         }
 
@@ -98,7 +133,7 @@ where
             links.push(OpdsLink::new(
                 OpdsLinkType::Navigation,
                 OpdsLinkRel::Next,
-                format!("/opds/v1.2/{}?page={}", href_postfix, page + 1),
+                format!("/opds/v1.2/{}{}page={}", href_postfix, separator, page + 1),
             ));
         }
 
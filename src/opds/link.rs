@@ -1,5 +1,9 @@
+use std::fmt;
+
 use serde::{self, Serialize};
 
+// Not every media type is linked to yet.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum OpdsLinkType {
     Acquisition, // "application/atom+xml;profile=opds-catalog;kind=acquisition",
@@ -9,11 +13,16 @@ pub enum OpdsLinkType {
     Zip,         // "application/zip"
     Epub,        // "application/epub+zip"
     Search,      // "application/opensearchdescription+xml"
+    Entry,       // "application/atom+xml;type=entry;profile=opds-catalog"
+    Azw3,        // "application/vnd.amazon.mobi8-ebook"
+    Mobi,        // "application/x-mobipocket-ebook"
+    Pdf,         // "application/pdf"
+    Html,        // "text/html"
 }
 
-impl ToString for OpdsLinkType {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for OpdsLinkType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             OpdsLinkType::Acquisition => {
                 "application/atom+xml;profile=opds-catalog;kind=acquisition"
             }
@@ -23,11 +32,17 @@ impl ToString for OpdsLinkType {
             OpdsLinkType::Zip => "application/zip",
             OpdsLinkType::Epub => "application/epub+zip",
             OpdsLinkType::Search => "application/opensearchdescription+xml",
-        }
-        .to_string()
+            OpdsLinkType::Entry => "application/atom+xml;type=entry;profile=opds-catalog",
+            OpdsLinkType::Azw3 => "application/vnd.amazon.mobi8-ebook",
+            OpdsLinkType::Mobi => "application/x-mobipocket-ebook",
+            OpdsLinkType::Pdf => "application/pdf",
+            OpdsLinkType::Html => "text/html",
+        })
     }
 }
 
+// Not every relation is linked with yet.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum OpdsLinkRel {
     ItSelf,      // self
@@ -40,11 +55,15 @@ pub enum OpdsLinkRel {
     Image,       // "http://opds-spec.org/image"
     PageStream,  // "http://vaemendis.net/opds-pse/stream"
     Search,      // "search"
+    Alternate,   // "alternate"
+    Related,     // "related"
+    Collection,  // "collection"
+    Facet,       // "http://opds-spec.org/facet"
 }
 
-impl ToString for OpdsLinkRel {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for OpdsLinkRel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
             OpdsLinkRel::ItSelf => "self",
             OpdsLinkRel::Subsection => "subsection",
             OpdsLinkRel::Acquisition => "http://opds-spec.org/acquisition",
@@ -55,19 +74,33 @@ impl ToString for OpdsLinkRel {
             OpdsLinkRel::Image => "http://opds-spec.org/image",
             OpdsLinkRel::PageStream => "http://vaemendis.net/opds-pse/stream",
             OpdsLinkRel::Search => "search",
-        }
-        .to_string()
+            OpdsLinkRel::Alternate => "alternate",
+            OpdsLinkRel::Related => "related",
+            OpdsLinkRel::Collection => "collection",
+            OpdsLinkRel::Facet => "http://opds-spec.org/facet",
+        })
     }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct OpdsLink {
-    #[serde(rename = "@link")]
-    link_type: String,
+    #[serde(rename = "@type")]
+    pub link_type: String,
     #[serde(rename = "@rel")]
-    rel: String,
+    pub rel: String,
     #[serde(rename = "@href")]
     pub href: String,
+    #[serde(rename = "@title", skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(rename = "@opds:facetGroup", skip_serializing_if = "Option::is_none")]
+    pub facet_group: Option<String>,
+    #[serde(
+        rename = "@opds:activeFacet",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub active_facet: bool,
+    #[serde(rename = "@thr:count", skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
 }
 
 impl OpdsLink {
@@ -76,8 +109,25 @@ impl OpdsLink {
             link_type: link_type.to_string(),
             rel: rel.to_string(),
             href,
+            title: None,
+            facet_group: None,
+            active_facet: false,
+            count: None,
         }
     }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Marks the link as a facet in the given group, with the number of entries it would show.
+    pub fn with_facet(mut self, group: &str, active: bool, count: Option<u32>) -> Self {
+        self.facet_group = Some(group.to_string());
+        self.active_facet = active;
+        self.count = count;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -91,6 +141,6 @@ mod tests {
             OpdsLinkRel::ItSelf,
             "test".to_string(),
         );
-        dbg!(quick_xml::se::to_string(&v));
+        dbg!(quick_xml::se::to_string(&v).unwrap());
     }
 }
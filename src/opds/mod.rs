@@ -1,9 +1,17 @@
 pub mod author;
+pub mod catalog;
+pub mod category;
 pub mod entry;
 pub mod feed;
 pub mod link;
+pub mod search;
+pub mod v2;
 
 pub use self::author::StumpAuthor;
-pub use self::entry::OpdsEntry;
+pub use self::catalog::CatalogEntry;
+pub use self::category::OpdsCategory;
+pub use self::entry::{OpdsEntry, OpdsStats, OpdsVisit};
 pub use self::feed::OpdsFeed;
 pub use self::link::{OpdsLink, OpdsLinkRel, OpdsLinkType};
+pub use self::search::{OpenSearchDescription, OpenSearchUrl};
+pub use self::v2::Opds2Feed;
@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+use super::OpdsLinkType;
+
+/// An OpenSearch description document, telling clients how to build search urls.
+#[derive(Debug, Serialize)]
+#[serde(rename = "OpenSearchDescription")]
+pub struct OpenSearchDescription {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: String,
+    #[serde(rename = "ShortName")]
+    pub short_name: String,
+    #[serde(rename = "Description")]
+    pub description: String,
+    #[serde(rename = "InputEncoding")]
+    pub input_encoding: String,
+    #[serde(rename = "OutputEncoding")]
+    pub output_encoding: String,
+    #[serde(rename = "Url")]
+    pub urls: Vec<OpenSearchUrl>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenSearchUrl {
+    #[serde(rename = "@type")]
+    url_type: String,
+    /// The url with `{searchTerms}` and optionally `{startPage?}` placeholders.
+    #[serde(rename = "@template")]
+    template: String,
+}
+
+impl OpenSearchDescription {
+    pub fn new(short_name: String, description: String, urls: Vec<OpenSearchUrl>) -> Self {
+        Self {
+            xmlns: "http://a9.com/-/spec/opensearch/1.1/".to_string(),
+            short_name,
+            description,
+            input_encoding: "UTF-8".to_string(),
+            output_encoding: "UTF-8".to_string(),
+            urls,
+        }
+    }
+}
+
+impl OpenSearchUrl {
+    pub fn new(url_type: OpdsLinkType, template: String) -> Self {
+        Self {
+            url_type: url_type.to_string(),
+            template,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opds::{OpdsLinkType, OpenSearchDescription, OpenSearchUrl};
+
+    #[test]
+    fn serializes_description() {
+        let description = OpenSearchDescription::new(
+            "Test".to_string(),
+            "Test search".to_string(),
+            vec![OpenSearchUrl::new(
+                OpdsLinkType::Acquisition,
+                "/search?q={searchTerms}".to_string(),
+            )],
+        );
+        assert_eq!(
+            quick_xml::se::to_string(&description).unwrap(),
+            concat!(
+                r#"<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">"#,
+                "<ShortName>Test</ShortName><Description>Test search</Description>",
+                "<InputEncoding>UTF-8</InputEncoding><OutputEncoding>UTF-8</OutputEncoding>",
+                r#"<Url type="application/atom+xml;profile=opds-catalog;kind=acquisition" template="/search?q={searchTerms}"/>"#,
+                "</OpenSearchDescription>",
+            )
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! OPDS 2.0 representation of the Atom based feeds, following the
+//! [OPDS 2.0 draft](https://drafts.opds.io/opds-2.0).
+//!
+//! Feeds are built as [`OpdsFeed`] and converted, so both versions share the same sources.
+
+use serde::Serialize;
+
+use super::{OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType, StumpAuthor};
+
+pub const OPDS2_FEED_TYPE: &str = "application/opds+json";
+pub const OPDS2_PUBLICATION_TYPE: &str = "application/opds-publication+json";
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Feed {
+    pub metadata: Opds2FeedMetadata,
+    pub links: Vec<Opds2Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Opds2Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub facets: Vec<Opds2Facet>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Opds2Publication>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Facet {
+    pub metadata: Opds2FacetMetadata,
+    pub links: Vec<Opds2Link>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2FacetMetadata {
+    pub title: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2FeedMetadata {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+    pub modified: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Link {
+    pub href: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub link_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub templated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<Opds2LinkProperties>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2LinkProperties {
+    #[serde(rename = "numberOfItems", skip_serializing_if = "Option::is_none")]
+    pub number_of_items: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Publication {
+    pub metadata: Opds2PublicationMetadata,
+    pub links: Vec<Opds2Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Opds2Link>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2PublicationMetadata {
+    #[serde(rename = "@type")]
+    pub schema_type: String,
+    pub identifier: String,
+    pub title: String,
+    pub modified: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub author: Vec<Opds2Contributor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subject: Vec<Opds2Subject>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Subject {
+    pub name: String,
+    pub scheme: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Opds2Contributor {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Opds2Link>,
+}
+
+/// Points links to our own feeds at their OPDS 2.0 counterpart.
+fn convert_href(href: &str) -> String {
+    match href.strip_prefix("/opds/v1.2/") {
+        Some(path) => format!("/opds/v2.0/{}", path),
+        None => href.to_string(),
+    }
+}
+
+fn is_feed_type(link_type: &str) -> bool {
+    link_type == OpdsLinkType::Navigation.to_string()
+        || link_type == OpdsLinkType::Acquisition.to_string()
+}
+
+impl From<&OpdsLink> for Opds2Link {
+    fn from(value: &OpdsLink) -> Self {
+        // OPDS 2.0 replaces OpenSearch description documents with templated links
+        if value.link_type == OpdsLinkType::Search.to_string() {
+            return Opds2Link {
+                href: "/opds/v2.0/search{?q}".to_string(),
+                link_type: Some(OPDS2_FEED_TYPE.to_string()),
+                rel: Some(OpdsLinkRel::Search.to_string()),
+                title: None,
+                templated: true,
+                properties: None,
+            };
+        }
+        let link_type = if is_feed_type(&value.link_type) {
+            OPDS2_FEED_TYPE.to_string()
+        } else if value.link_type == OpdsLinkType::Entry.to_string() {
+            OPDS2_PUBLICATION_TYPE.to_string()
+        } else {
+            value.link_type.clone()
+        };
+        Opds2Link {
+            href: convert_href(&value.href),
+            link_type: Some(link_type),
+            rel: Some(value.rel.clone()),
+            title: value.title.clone(),
+            templated: false,
+            properties: value.count.map(|count| Opds2LinkProperties {
+                number_of_items: Some(count),
+            }),
+        }
+    }
+}
+
+impl From<&StumpAuthor> for Opds2Contributor {
+    fn from(value: &StumpAuthor) -> Self {
+        Opds2Contributor {
+            name: value.name.clone(),
+            links: value
+                .uri
+                .iter()
+                .map(|uri| Opds2Link {
+                    href: convert_href(uri),
+                    link_type: Some(OPDS2_FEED_TYPE.to_string()),
+                    rel: None,
+                    title: None,
+                    templated: false,
+                    properties: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&OpdsEntry> for Opds2Publication {
+    fn from(value: &OpdsEntry) -> Self {
+        let image_rels = [
+            OpdsLinkRel::Image.to_string(),
+            OpdsLinkRel::Thumbnail.to_string(),
+        ];
+        let (images, links): (Vec<_>, Vec<_>) = value
+            .links
+            .iter()
+            .partition(|link| image_rels.contains(&link.rel));
+        Opds2Publication {
+            metadata: Opds2PublicationMetadata {
+                schema_type: "http://schema.org/Book".to_string(),
+                identifier: value.id.clone(),
+                title: value.title.clone(),
+                modified: value.updated.clone(),
+                published: value.published.clone(),
+                language: value.language.clone(),
+                description: value.summary.clone().or_else(|| value.content.clone()),
+                author: value
+                    .authors
+                    .iter()
+                    .flatten()
+                    .map(Opds2Contributor::from)
+                    .collect(),
+                subject: value
+                    .categories
+                    .iter()
+                    .map(|category| Opds2Subject {
+                        name: category
+                            .label
+                            .clone()
+                            .unwrap_or_else(|| category.term.clone()),
+                        scheme: category.scheme.clone(),
+                        code: category.term.clone(),
+                    })
+                    .collect(),
+            },
+            links: links.into_iter().map(Opds2Link::from).collect(),
+            images: images.into_iter().map(Opds2Link::from).collect(),
+        }
+    }
+}
+
+impl From<&OpdsFeed> for Opds2Feed {
+    fn from(value: &OpdsFeed) -> Self {
+        let mut navigation = Vec::new();
+        let mut publications = Vec::new();
+        for entry in &value.entries {
+            // entries which only link to other feeds are navigation, everything else is a publication
+            let is_navigation = !entry.links.is_empty()
+                && entry.links.iter().all(|link| {
+                    is_feed_type(&link.link_type)
+                        || link.link_type == OpdsLinkType::Search.to_string()
+                });
+            if is_navigation {
+                navigation.extend(entry.links.iter().map(|link| Opds2Link {
+                    title: Some(entry.title.clone()),
+                    ..link.into()
+                }));
+            } else {
+                publications.push(entry.into());
+            }
+        }
+
+        // facet links are grouped by their facet group instead of listed with the feed links
+        let mut links = Vec::new();
+        let mut facets: Vec<Opds2Facet> = Vec::new();
+        for link in value.links.iter().flatten() {
+            let Some(group) = &link.facet_group else {
+                links.push(link.into());
+                continue;
+            };
+            match facets.iter_mut().find(|f| &f.metadata.title == group) {
+                Some(facet) => facet.links.push(link.into()),
+                None => facets.push(Opds2Facet {
+                    metadata: Opds2FacetMetadata {
+                        title: group.clone(),
+                    },
+                    links: vec![link.into()],
+                }),
+            }
+        }
+
+        Opds2Feed {
+            metadata: Opds2FeedMetadata {
+                title: value.title.clone(),
+                subtitle: value.subtitle.clone(),
+                modified: value.updated.clone(),
+            },
+            links,
+            navigation,
+            facets,
+            publications,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use serde_json::json;
+
+    use super::convert_href;
+    use crate::opds::{
+        CatalogEntry, Opds2Feed, OpdsEntry, OpdsFeed, OpdsLink, OpdsLinkRel, OpdsLinkType,
+    };
+
+    #[test]
+    fn splits_navigation_and_publications() {
+        let mut feed = OpdsFeed::navigation(
+            "catalog",
+            "Catalog",
+            "catalog",
+            &[CatalogEntry {
+                id: "history",
+                title: "History",
+                description: "History",
+                href: "/opds/v1.2/history?page=1",
+                kind: OpdsLinkType::Acquisition,
+            }],
+        );
+        feed.entries.push(OpdsEntry::new(
+            "/works/1".to_string(),
+            DateTime::parse_from_rfc3339("2023-01-02T00:00:00+00:00").unwrap(),
+            "Work".to_string(),
+            None,
+            None,
+            Some(vec![OpdsLink::new(
+                OpdsLinkType::Epub,
+                OpdsLinkRel::Acquisition,
+                "https://archiveofourown.org/downloads/1/a.epub".to_string(),
+            )]),
+        ));
+
+        let feed = Opds2Feed::from(&feed);
+        let mut json = serde_json::to_value(&feed).unwrap();
+        // the feed is always modified now
+        json["metadata"].as_object_mut().unwrap().remove("modified");
+        assert_eq!(
+            json,
+            json!({
+                "metadata": {"title": "Catalog"},
+                "links": [
+                    {"href": "/opds/v2.0/catalog", "type": "application/opds+json", "rel": "self"},
+                    {"href": "/opds/v2.0/catalog", "type": "application/opds+json", "rel": "start"},
+                ],
+                "navigation": [{
+                    "href": "/opds/v2.0/history?page=1",
+                    "type": "application/opds+json",
+                    "rel": "subsection",
+                    "title": "History",
+                }],
+                "publications": [{
+                    "metadata": {
+                        "@type": "http://schema.org/Book",
+                        "identifier": "/works/1",
+                        "title": "Work",
+                        "modified": "2023-01-02T00:00:00+00:00",
+                    },
+                    "links": [{
+                        "href": "https://archiveofourown.org/downloads/1/a.epub",
+                        "type": "application/epub+zip",
+                        "rel": "http://opds-spec.org/acquisition",
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn converts_feed_hrefs() {
+        assert_eq!(
+            convert_href("/opds/v1.2/search?q=a&page=2"),
+            "/opds/v2.0/search?q=a&page=2"
+        );
+        assert_eq!(convert_href("/opds/v1.2"), "/opds/v1.2");
+        assert_eq!(
+            convert_href("https://archiveofourown.org/works/1"),
+            "https://archiveofourown.org/works/1"
+        );
+    }
+}